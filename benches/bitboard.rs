@@ -0,0 +1,46 @@
+use bimaru::bitboard::{fleet, of_cells, Bitboard};
+use bimaru::setups::{cells, from_cells, is_valid, OccupiedCells, GAME_1};
+use bimaru::{Check, IsSolved};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn counts(c: &mut Criterion) {
+    let board = Bitboard::from_setup(&GAME_1).unwrap();
+    let mut group = c.benchmark_group("counts");
+    group.bench_function("setup", |b| {
+        b.iter(|| (black_box(&GAME_1).occupied_rows(), GAME_1.occupied_cols()))
+    });
+    group.bench_function("bitboard", |b| {
+        b.iter(|| (black_box(&board).occupied_rows(), board.occupied_cols()))
+    });
+    group.finish();
+}
+
+fn touch(c: &mut Criterion) {
+    let board = Bitboard::from_setup(&GAME_1).unwrap();
+    let mut group = c.benchmark_group("touch");
+    group.bench_function("setup", |b| b.iter(|| is_valid(black_box(&GAME_1))));
+    group.bench_function("bitboard", |b| b.iter(|| black_box(&board).is_valid()));
+    group.finish();
+}
+
+fn fleet_detection(c: &mut Criterion) {
+    let cells = cells(&GAME_1);
+    let mask = of_cells(&cells).unwrap();
+    let mut group = c.benchmark_group("fleet");
+    group.bench_function("setup", |b| b.iter(|| from_cells(black_box(&cells))));
+    group.bench_function("bitboard", |b| b.iter(|| fleet(black_box(mask))));
+    group.finish();
+}
+
+fn solves(c: &mut Criterion) {
+    let check = Check::from_cells(&cells(&GAME_1));
+    let board = Bitboard::from_setup(&GAME_1).unwrap();
+    let mask = of_cells(&cells(&GAME_1)).unwrap();
+    let mut group = c.benchmark_group("solves");
+    group.bench_function("setup", |b| b.iter(|| black_box(&check).solves(GAME_1)));
+    group.bench_function("bitboard", |b| b.iter(|| board.solves(black_box(mask))));
+    group.finish();
+}
+
+criterion_group!(benches, counts, touch, fleet_detection, solves);
+criterion_main!(benches);
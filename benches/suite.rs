@@ -0,0 +1,104 @@
+use async_std::task::block_on;
+use bimaru::deduce::explain;
+use bimaru::search::{solve, Puzzle};
+use bimaru::setups::{cells, OccupiedCells, GAME_1, GAME_2};
+use bimaru::{build_app, find_hints, render_board, Check, IsSolved, MAX_HINTS};
+use bimaru_protocol::{to_non_empty_list, NestedNewGame, NewGame, SESSION_HEADER};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use tide_testing::TideTestingExt;
+use uuid::uuid;
+
+fn setups(c: &mut Criterion) {
+    let check = Check::from_cells(&cells(&GAME_1));
+    c.bench_function("find_hints", |b| {
+        b.iter(|| find_hints(black_box(&GAME_1), MAX_HINTS))
+    });
+    c.bench_function("render_board", |b| {
+        b.iter(|| render_board(black_box(&GAME_1)))
+    });
+    c.bench_function("check_solves", |b| {
+        b.iter(|| black_box(&check).solves(GAME_1))
+    });
+}
+
+fn yaml(c: &mut Criterion) {
+    let game_setup_id = uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+    let game = NewGame {
+        game_setup_id,
+        number_of_hints: MAX_HINTS,
+        occupied_cols: GAME_1.occupied_cols(),
+        occupied_rows: GAME_1.occupied_rows(),
+    };
+    let nested = NestedNewGame {
+        game_setup_id,
+        number_of_hints: MAX_HINTS,
+        occupied_cols: to_non_empty_list(&mut GAME_1.occupied_cols().to_vec()),
+        occupied_rows: to_non_empty_list(&mut GAME_1.occupied_rows().to_vec()),
+    };
+    let mut group = c.benchmark_group("yaml");
+    group.bench_function("new_game", |b| {
+        b.iter(|| serde_yaml::to_string(black_box(&game)).unwrap())
+    });
+    group.bench_function("nested_new_game", |b| {
+        b.iter(|| serde_yaml::to_string(black_box(&nested)).unwrap())
+    });
+    group.finish();
+}
+
+fn solver(c: &mut Criterion) {
+    let hints = find_hints(&GAME_1, MAX_HINTS);
+    let mut group = c.benchmark_group("solver");
+    group.sample_size(10);
+    group.bench_function("search", |b| {
+        b.iter(|| solve(black_box(&Puzzle::from_setup(&GAME_2))))
+    });
+    group.bench_function("explain", |b| {
+        b.iter(|| explain(black_box(&GAME_1), &hints))
+    });
+    group.finish();
+}
+
+// a whole game in process: create, hint, check and the board, each on a
+// fresh app so the sessions of earlier iterations do not pile up
+fn end_to_end(c: &mut Criterion) {
+    let id = uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+    let solution = serde_yaml::to_string(&Check::from_cells(&cells(&GAME_1))).unwrap();
+    let mut group = c.benchmark_group("routes");
+    group.bench_function("game", |b| {
+        b.iter_batched(
+            build_app,
+            |app| {
+                block_on(async {
+                    let resp = app.post(format!("/game/{}", id)).await.unwrap();
+                    let session_id = resp[SESSION_HEADER].last().to_string();
+                    app.get(format!("/game/{}/hint?limit=3", id))
+                        .header(SESSION_HEADER, session_id.as_str())
+                        .recv_string()
+                        .await
+                        .unwrap();
+                    let answer = app
+                        .post(format!("/game/{}/check", id))
+                        .header(SESSION_HEADER, session_id.as_str())
+                        .body_string(solution.clone())
+                        .recv_string()
+                        .await
+                        .unwrap();
+                    assert_eq!(answer, "Well done!");
+                    app.get(format!("/game/{}/board", id))
+                        .recv_string()
+                        .await
+                        .unwrap()
+                })
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    let app = build_app();
+    group.bench_function("board", |b| {
+        b.iter(|| block_on(app.get(format!("/game/{}/board", id)).recv_string()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, setups, yaml, solver, end_to_end);
+criterion_main!(benches);
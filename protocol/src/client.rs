@@ -0,0 +1,135 @@
+use crate::{Check, Coord, EncodingStyle, NestedNewGame, NewGame, WireFormat, SESSION_HEADER};
+use serde::Serialize;
+use std::str::FromStr;
+use surf::http::Url;
+use surf::{Config, RequestBuilder, Response, StatusCode};
+use uuid::Uuid;
+
+// A game as seen by the client, whatever create and wire format the setup uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Game {
+    pub game_setup_id: Uuid,
+    pub session_id: Option<Uuid>,
+    pub wire_format: WireFormat,
+    pub number_of_hints: u8,
+    pub occupied_rows: Vec<u8>,
+    pub occupied_cols: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct PlayerQuery<'a> {
+    player: &'a str,
+}
+
+pub struct Client {
+    http: surf::Client,
+}
+
+impl From<surf::Client> for Client {
+    fn from(http: surf::Client) -> Client {
+        Client { http }
+    }
+}
+
+impl Client {
+    pub fn new(base_url: &str) -> surf::Result<Client> {
+        // paths are joined relative to the base, so it has to end in a slash
+        let base_url = if base_url.ends_with('/') {
+            Url::parse(base_url)?
+        } else {
+            Url::parse(&format!("{}/", base_url))?
+        };
+        let http: surf::Client = Config::new().set_base_url(base_url).try_into()?;
+        Ok(Client { http })
+    }
+
+    pub async fn create_game(&self, setup_id: &Uuid, player: Option<&str>) -> surf::Result<Game> {
+        let mut req = self.http.post(format!("game/{}", setup_id));
+        if let Some(player) = player {
+            req = req.query(&PlayerQuery { player })?;
+        }
+        let mut resp = expect_success(req.await?).await?;
+        let session_id = resp
+            .header(SESSION_HEADER)
+            .and_then(|v| Uuid::from_str(v.last().as_str()).ok());
+        let (wire_format, nested) = format_of(&resp);
+        let body = resp.body_bytes().await?;
+        if nested {
+            let entity = wire_format.decode::<NestedNewGame>(&body)?;
+            Ok(Game {
+                game_setup_id: entity.game_setup_id,
+                session_id,
+                wire_format,
+                number_of_hints: entity.number_of_hints,
+                occupied_rows: entity.occupied_rows.map(|l| l.to_vec()).unwrap_or_default(),
+                occupied_cols: entity.occupied_cols.map(|l| l.to_vec()).unwrap_or_default(),
+            })
+        } else {
+            let entity = wire_format.decode::<NewGame>(&body)?;
+            Ok(Game {
+                game_setup_id: entity.game_setup_id,
+                session_id,
+                wire_format,
+                number_of_hints: entity.number_of_hints,
+                occupied_rows: entity.occupied_rows.to_vec(),
+                occupied_cols: entity.occupied_cols.to_vec(),
+            })
+        }
+    }
+
+    pub async fn hint(&self, game: &Game, limit: u8) -> surf::Result<Vec<Coord>> {
+        let req = self
+            .http
+            .get(format!("game/{}/hint?limit={}", game.game_setup_id, limit));
+        let mut resp = expect_success(with_session(req, game).await?).await?;
+        let (wire_format, style) = encoding_of(&resp);
+        let body = resp.body_bytes().await?;
+        Ok(style.decode_coords(wire_format, &body)?)
+    }
+
+    // true when the server accepts the solution
+    pub async fn check(&self, game: &Game, check: &Check) -> surf::Result<bool> {
+        let req = self
+            .http
+            .post(format!("game/{}/check", game.game_setup_id))
+            .body_bytes(game.wire_format.encode(check)?)
+            .content_type(game.wire_format.content_type(false));
+        let mut resp = expect_success(with_session(req, game).await?).await?;
+        Ok(resp.body_string().await? == "Well done!")
+    }
+
+    pub async fn board(&self, setup_id: &Uuid) -> surf::Result<String> {
+        let mut resp =
+            expect_success(self.http.get(format!("game/{}/board", setup_id)).await?).await?;
+        resp.body_string().await
+    }
+}
+
+fn with_session(req: RequestBuilder, game: &Game) -> RequestBuilder {
+    match game.session_id {
+        Some(session_id) => req.header(SESSION_HEADER, session_id.to_string()),
+        None => req,
+    }
+}
+
+fn format_of(resp: &Response) -> (WireFormat, bool) {
+    resp.content_type()
+        .and_then(|ct| WireFormat::from_content_type(ct.essence()))
+        .unwrap_or((WireFormat::Yaml, false))
+}
+
+fn encoding_of(resp: &Response) -> (WireFormat, EncodingStyle) {
+    resp.content_type()
+        .and_then(|ct| EncodingStyle::from_content_type(&ct.to_string()))
+        .unwrap_or_default()
+}
+
+async fn expect_success(mut resp: Response) -> surf::Result<Response> {
+    if resp.status().is_success() {
+        Ok(resp)
+    } else {
+        let status: StatusCode = resp.status();
+        let text = resp.body_string().await.unwrap_or_default();
+        Err(surf::Error::from_str(status, text))
+    }
+}
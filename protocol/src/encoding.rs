@@ -0,0 +1,470 @@
+use crate::{to_non_empty_list, Coord, NonEmptyList, WireError, WireFormat};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const SIZE: usize = 10;
+const SHIP: char = '#';
+const WATER: char = '.';
+
+// How a list of cells is written on the wire, selected per setup.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub enum EncodingStyle {
+    #[default]
+    #[serde(rename = "list")]
+    FlatList,
+    #[serde(rename = "nested")]
+    ConsList,
+    #[serde(rename = "run-length")]
+    RunLength,
+    #[serde(rename = "bitmap")]
+    Bitmap,
+    #[serde(rename = "a1")]
+    A1,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct EncodingError(String);
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+fn encoding_error(text: String) -> EncodingError {
+    EncodingError(text)
+}
+
+pub trait Encoding {
+    const STYLE: EncodingStyle;
+    type Encoded: Serialize + DeserializeOwned + JsonSchema;
+
+    fn encode(coords: &[Coord]) -> Result<Self::Encoded, EncodingError>;
+    fn decode(encoded: Self::Encoded) -> Result<Vec<Coord>, EncodingError>;
+}
+
+// The entity carrying the encoded cells, as in hints and check bodies.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Coords<T> {
+    pub coords: T,
+}
+
+pub struct FlatList;
+pub struct ConsList;
+pub struct RunLength;
+pub struct Bitmap;
+pub struct A1;
+
+impl Encoding for FlatList {
+    const STYLE: EncodingStyle = EncodingStyle::FlatList;
+    type Encoded = Vec<Coord>;
+
+    fn encode(coords: &[Coord]) -> Result<Vec<Coord>, EncodingError> {
+        Ok(coords.to_vec())
+    }
+
+    fn decode(encoded: Vec<Coord>) -> Result<Vec<Coord>, EncodingError> {
+        Ok(encoded)
+    }
+}
+
+impl Encoding for ConsList {
+    const STYLE: EncodingStyle = EncodingStyle::ConsList;
+    type Encoded = Option<NonEmptyList<Coord>>;
+
+    fn encode(coords: &[Coord]) -> Result<Option<NonEmptyList<Coord>>, EncodingError> {
+        Ok(to_non_empty_list(&mut coords.to_vec()))
+    }
+
+    fn decode(encoded: Option<NonEmptyList<Coord>>) -> Result<Vec<Coord>, EncodingError> {
+        Ok(encoded.map(|l| l.to_vec()).unwrap_or_default())
+    }
+}
+
+// The styles other than the lists only have room for cells on the board.
+fn check_on_board(coords: &[Coord]) -> Result<(), EncodingError> {
+    match coords.iter().find(|c| c.row >= SIZE || c.col >= SIZE) {
+        Some(c) => Err(encoding_error(format!(
+            "Cell {},{} is off the board",
+            c.row, c.col
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn to_rows(coords: &[Coord]) -> Result<[[bool; SIZE]; SIZE], EncodingError> {
+    check_on_board(coords)?;
+    let mut rows = [[false; SIZE]; SIZE];
+    for c in coords {
+        rows[c.row][c.col] = true;
+    }
+    Ok(rows)
+}
+
+fn from_rows(rows: &[[bool; SIZE]; SIZE]) -> Vec<Coord> {
+    let mut coords = vec![];
+    for (row, cells) in rows.iter().enumerate() {
+        for (col, ship) in cells.iter().enumerate() {
+            if *ship {
+                coords.push(Coord { col, row });
+            }
+        }
+    }
+    coords
+}
+
+fn check_row_count(encoded: &[String]) -> Result<(), EncodingError> {
+    if encoded.len() == SIZE {
+        Ok(())
+    } else {
+        Err(encoding_error(format!(
+            "Expected {} rows, got {}",
+            SIZE,
+            encoded.len()
+        )))
+    }
+}
+
+fn cell_of(ch: char) -> Result<bool, EncodingError> {
+    match ch {
+        SHIP => Ok(true),
+        WATER => Ok(false),
+        _ => Err(encoding_error(format!("Unknown cell '{}'", ch))),
+    }
+}
+
+// One string per row such as "2.3#5.", a count followed by the cell.
+impl Encoding for RunLength {
+    const STYLE: EncodingStyle = EncodingStyle::RunLength;
+    type Encoded = Vec<String>;
+
+    fn encode(coords: &[Coord]) -> Result<Vec<String>, EncodingError> {
+        Ok(to_rows(coords)?
+            .iter()
+            .map(|cells| {
+                let mut runs = String::new();
+                let mut start = 0;
+                for i in 1..=SIZE {
+                    if i == SIZE || cells[i] != cells[start] {
+                        let ch = if cells[start] { SHIP } else { WATER };
+                        runs.push_str(&format!("{}{}", i - start, ch));
+                        start = i;
+                    }
+                }
+                runs
+            })
+            .collect())
+    }
+
+    fn decode(encoded: Vec<String>) -> Result<Vec<Coord>, EncodingError> {
+        check_row_count(&encoded)?;
+        let mut rows = [[false; SIZE]; SIZE];
+        for (row, runs) in encoded.iter().enumerate() {
+            let mut col = 0;
+            let mut count = String::new();
+            for ch in runs.chars() {
+                if ch.is_ascii_digit() {
+                    count.push(ch);
+                    continue;
+                }
+                let ship = cell_of(ch)?;
+                let n: usize = count
+                    .parse()
+                    .map_err(|_| encoding_error(format!("Missing count in row {}", row)))?;
+                if n == 0 || n > SIZE - col {
+                    return Err(encoding_error(format!("Row {} is not {} long", row, SIZE)));
+                }
+                for cell in rows[row][col..col + n].iter_mut() {
+                    *cell = ship;
+                }
+                col += n;
+                count.clear();
+            }
+            if col != SIZE || !count.is_empty() {
+                return Err(encoding_error(format!("Row {} is not {} long", row, SIZE)));
+            }
+        }
+        Ok(from_rows(&rows))
+    }
+}
+
+// One string per row such as "..###.....".
+impl Encoding for Bitmap {
+    const STYLE: EncodingStyle = EncodingStyle::Bitmap;
+    type Encoded = Vec<String>;
+
+    fn encode(coords: &[Coord]) -> Result<Vec<String>, EncodingError> {
+        Ok(to_rows(coords)?
+            .iter()
+            .map(|cells| {
+                cells
+                    .iter()
+                    .map(|ship| if *ship { SHIP } else { WATER })
+                    .collect()
+            })
+            .collect())
+    }
+
+    fn decode(encoded: Vec<String>) -> Result<Vec<Coord>, EncodingError> {
+        check_row_count(&encoded)?;
+        let mut rows = [[false; SIZE]; SIZE];
+        for (row, cells) in encoded.iter().enumerate() {
+            if cells.chars().count() != SIZE {
+                return Err(encoding_error(format!("Row {} is not {} long", row, SIZE)));
+            }
+            for (col, ch) in cells.chars().enumerate() {
+                rows[row][col] = cell_of(ch)?;
+            }
+        }
+        Ok(from_rows(&rows))
+    }
+}
+
+// Spreadsheet style, the column as a letter and the row counted from 1: "C7".
+impl Encoding for A1 {
+    const STYLE: EncodingStyle = EncodingStyle::A1;
+    type Encoded = Vec<String>;
+
+    fn encode(coords: &[Coord]) -> Result<Vec<String>, EncodingError> {
+        check_on_board(coords)?;
+        Ok(coords
+            .iter()
+            .map(|c| format!("{}{}", (b'A' + c.col as u8) as char, c.row + 1))
+            .collect())
+    }
+
+    fn decode(encoded: Vec<String>) -> Result<Vec<Coord>, EncodingError> {
+        encoded
+            .iter()
+            .map(|cell| {
+                let mut chars = cell.chars();
+                let col = match chars.next() {
+                    Some(letter @ 'A'..='J') => letter as usize - 'A' as usize,
+                    _ => return Err(encoding_error(format!("Bad column in '{}'", cell))),
+                };
+                match chars.as_str().parse::<usize>() {
+                    Ok(row) if (1..=SIZE).contains(&row) => Ok(Coord { col, row: row - 1 }),
+                    _ => Err(encoding_error(format!("Bad row in '{}'", cell))),
+                }
+            })
+            .collect()
+    }
+}
+
+fn encode_with<E: Encoding>(format: WireFormat, coords: &[Coord]) -> Result<Vec<u8>, WireError> {
+    format.encode(&Coords {
+        coords: E::encode(coords).map_err(|e| WireError(e.to_string()))?,
+    })
+}
+
+fn decode_with<E: Encoding>(format: WireFormat, bytes: &[u8]) -> Result<Vec<Coord>, WireError> {
+    let entity = format.decode::<Coords<E::Encoded>>(bytes)?;
+    E::decode(entity.coords).map_err(|e| WireError(e.to_string()))
+}
+
+impl EncodingStyle {
+    pub const ALL: [EncodingStyle; 5] = [
+        EncodingStyle::FlatList,
+        EncodingStyle::ConsList,
+        EncodingStyle::RunLength,
+        EncodingStyle::Bitmap,
+        EncodingStyle::A1,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EncodingStyle::FlatList => "list",
+            EncodingStyle::ConsList => "nested",
+            EncodingStyle::RunLength => "run-length",
+            EncodingStyle::Bitmap => "bitmap",
+            EncodingStyle::A1 => "a1",
+        }
+    }
+
+    // The flat and cons lists keep their original content types, the other
+    // styles are named in a parameter.
+    pub fn content_type(self, format: WireFormat) -> String {
+        match self {
+            EncodingStyle::FlatList => format.content_type(false).to_string(),
+            EncodingStyle::ConsList => format.content_type(true).to_string(),
+            _ => format!("{}; encoding={}", format.content_type(false), self.name()),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EncodingStyle> {
+        EncodingStyle::ALL.into_iter().find(|s| s.name() == name)
+    }
+
+    // The name in an encoding= parameter, whether or not a style has it.
+    pub fn parameter(content_type: &str) -> Option<&str> {
+        content_type
+            .split(';')
+            .skip(1)
+            .find_map(|param| param.trim().strip_prefix("encoding="))
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<(WireFormat, EncodingStyle)> {
+        let essence = content_type.split(';').next()?.trim();
+        let (format, nested) = WireFormat::from_content_type(essence)?;
+        let named = EncodingStyle::parameter(content_type).and_then(EncodingStyle::from_name);
+        match (named, nested) {
+            (Some(style), _) => Some((format, style)),
+            (None, true) => Some((format, EncodingStyle::ConsList)),
+            (None, false) => Some((format, EncodingStyle::FlatList)),
+        }
+    }
+
+    pub fn encode_coords(self, format: WireFormat, coords: &[Coord]) -> Result<Vec<u8>, WireError> {
+        match self {
+            EncodingStyle::FlatList => encode_with::<FlatList>(format, coords),
+            EncodingStyle::ConsList => encode_with::<ConsList>(format, coords),
+            EncodingStyle::RunLength => encode_with::<RunLength>(format, coords),
+            EncodingStyle::Bitmap => encode_with::<Bitmap>(format, coords),
+            EncodingStyle::A1 => encode_with::<A1>(format, coords),
+        }
+    }
+
+    pub fn decode_coords(self, format: WireFormat, bytes: &[u8]) -> Result<Vec<Coord>, WireError> {
+        match self {
+            EncodingStyle::FlatList => decode_with::<FlatList>(format, bytes),
+            EncodingStyle::ConsList => decode_with::<ConsList>(format, bytes),
+            EncodingStyle::RunLength => decode_with::<RunLength>(format, bytes),
+            EncodingStyle::Bitmap => decode_with::<Bitmap>(format, bytes),
+            EncodingStyle::A1 => decode_with::<A1>(format, bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<Coord> {
+        vec![
+            Coord { col: 0, row: 0 },
+            Coord { col: 1, row: 0 },
+            Coord { col: 9, row: 0 },
+            Coord { col: 4, row: 6 },
+            Coord { col: 9, row: 9 },
+        ]
+    }
+
+    #[test]
+    fn test_styles() {
+        assert_eq!(
+            RunLength::encode(&sample()).unwrap()[0..2],
+            ["2#7.1#".to_string(), "10.".to_string()]
+        );
+        assert_eq!(Bitmap::encode(&sample()).unwrap()[6], "....#.....");
+        assert_eq!(
+            A1::encode(&sample()).unwrap(),
+            vec!["A1", "B1", "J1", "E7", "J10"]
+        );
+        assert_eq!(
+            ConsList::encode(&sample()).unwrap().unwrap().head,
+            Coord { col: 0, row: 0 }
+        );
+    }
+
+    #[test]
+    fn test_round_trips() {
+        for style in EncodingStyle::ALL {
+            for format in WireFormat::ALL {
+                let bytes = style.encode_coords(format, &sample()).unwrap();
+                assert_eq!(style.decode_coords(format, &bytes).unwrap(), sample());
+                let bytes = style.encode_coords(format, &[]).unwrap();
+                assert_eq!(style.decode_coords(format, &bytes).unwrap(), vec![]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edges() {
+        let corners = vec![
+            Coord { col: 9, row: 0 },
+            Coord { col: 0, row: 9 },
+            Coord { col: 9, row: 9 },
+        ];
+        for style in EncodingStyle::ALL {
+            for format in WireFormat::ALL {
+                let bytes = style.encode_coords(format, &corners).unwrap();
+                assert_eq!(style.decode_coords(format, &bytes).unwrap(), corners);
+            }
+        }
+        // past the board only the lists can carry a cell
+        for off in [
+            Coord { col: 10, row: 0 },
+            Coord { col: 0, row: 10 },
+            Coord { col: 191, row: 0 },
+            Coord { col: 300, row: 300 },
+        ] {
+            for style in EncodingStyle::ALL {
+                let encoded = style.encode_coords(WireFormat::Yaml, &[off]);
+                match style {
+                    EncodingStyle::FlatList | EncodingStyle::ConsList => {
+                        let bytes = encoded.unwrap();
+                        assert_eq!(
+                            style.decode_coords(WireFormat::Yaml, &bytes).unwrap(),
+                            vec![off]
+                        );
+                    }
+                    _ => assert!(encoded.is_err(), "{:?} {:?}", style, off),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut rows = Bitmap::encode(&sample()).unwrap();
+        assert!(RunLength::decode(vec!["10.".to_string()]).is_err());
+        rows[3] = "..x.......".to_string();
+        assert!(Bitmap::decode(rows.clone()).is_err());
+        rows[3] = "...".to_string();
+        assert!(Bitmap::decode(rows).is_err());
+        let mut runs = RunLength::encode(&sample()).unwrap();
+        for broken in [
+            "9.",
+            "11.",
+            "#9.",
+            "5.5",
+            "0#10.",
+            "1.18446744073709551615#",
+        ] {
+            runs[1] = broken.to_string();
+            assert!(RunLength::decode(runs.clone()).is_err(), "{}", broken);
+        }
+        for broken in ["K1", "A0", "A11", "a1", ""] {
+            assert!(A1::decode(vec![broken.to_string()]).is_err(), "{}", broken);
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            EncodingStyle::from_content_type("text/x-yaml;encoding=bitmap"),
+            Some((WireFormat::Yaml, EncodingStyle::Bitmap))
+        );
+        assert_eq!(EncodingStyle::from_content_type("text/plain"), None);
+        assert_eq!(
+            EncodingStyle::parameter("text/x-yaml; encoding=morse"),
+            Some("morse")
+        );
+        assert_eq!(EncodingStyle::parameter("text/x-yaml"), None);
+        for style in EncodingStyle::ALL {
+            assert_eq!(EncodingStyle::from_name(style.name()), Some(style));
+            for format in WireFormat::ALL {
+                assert_eq!(
+                    EncodingStyle::from_content_type(&style.content_type(format)),
+                    Some((format, style))
+                );
+            }
+            let yaml = serde_yaml::to_string(&style).unwrap();
+            assert_eq!(yaml.trim(), style.name());
+        }
+    }
+}
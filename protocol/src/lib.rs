@@ -0,0 +1,333 @@
+mod client;
+mod encoding;
+pub mod v2;
+mod wire;
+
+pub use client::{Client, Game};
+pub use encoding::{
+    Bitmap, ConsList, Coords, Encoding, EncodingError, EncodingStyle, FlatList, RunLength, A1,
+};
+pub use wire::{check_nesting, WireError, WireFormat, MAX_NESTING};
+
+use schemars::JsonSchema;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+use uuid::Uuid;
+
+pub const CT_YAML: &str = "text/x-yaml";
+pub const CT_YAML_NESTED: &str = "text/x-yaml-nested-lists";
+pub const CT_PLAIN: &str = "text/plain";
+pub const SESSION_HEADER: &str = "X-Session-Id";
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct NewGame {
+    #[serde(with = "uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub game_setup_id: Uuid,
+    pub number_of_hints: u8,
+    pub occupied_rows: [u8; 10],
+    pub occupied_cols: [u8; 10],
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct NestedNewGame {
+    #[serde(with = "uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub game_setup_id: Uuid,
+    pub number_of_hints: u8,
+    pub occupied_rows: Option<NonEmptyList<u8>>,
+    pub occupied_cols: Option<NonEmptyList<u8>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Check {
+    pub coords: Vec<Coord>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Hints {
+    pub coords: Vec<Coord>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct NestedHints {
+    pub coords: Option<NonEmptyList<Coord>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone, Copy)]
+pub struct Coord {
+    pub col: usize,
+    pub row: usize,
+}
+
+impl Check {
+    pub fn from_cells(cells: &[(usize, usize)]) -> Check {
+        Check {
+            coords: cells.iter().map(|&(row, col)| Coord { col, row }).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema, PartialEq)]
+pub struct NonEmptyList<T> {
+    pub head: T,
+    pub tail: Option<Box<NonEmptyList<T>>>,
+}
+
+// Every cell of a board, longer lists are refused before they nest deep
+// enough to exhaust the stack.
+pub const MAX_LIST_DEPTH: usize = 100;
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for NonEmptyList<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        ListSeed::<T>::at(1).deserialize(deserializer)
+    }
+}
+
+struct ListSeed<T> {
+    depth: usize,
+    marker: PhantomData<T>,
+}
+
+struct TailSeed<T>(ListSeed<T>);
+
+impl<T> ListSeed<T> {
+    fn at(depth: usize) -> ListSeed<T> {
+        ListSeed {
+            depth,
+            marker: PhantomData,
+        }
+    }
+
+    fn tail(&self) -> TailSeed<T> {
+        TailSeed(ListSeed::at(self.depth + 1))
+    }
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for ListSeed<T> {
+    type Value = NonEmptyList<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if self.depth > MAX_LIST_DEPTH {
+            return Err(de::Error::custom(format!(
+                "list is longer than {}",
+                MAX_LIST_DEPTH
+            )));
+        }
+        deserializer.deserialize_struct("NonEmptyList", &["head", "tail"], self)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for ListSeed<T> {
+    type Value = NonEmptyList<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list with a head and an optional tail")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut head = None;
+        let mut tail = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "head" => head = Some(map.next_value()?),
+                "tail" => tail = map.next_value_seed(self.tail())?,
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        match head {
+            Some(head) => Ok(NonEmptyList { head, tail }),
+            None => Err(de::Error::missing_field("head")),
+        }
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        match seq.next_element()? {
+            Some(head) => Ok(NonEmptyList {
+                head,
+                tail: seq.next_element_seed(self.tail())?.flatten(),
+            }),
+            None => Err(de::Error::invalid_length(0, &self)),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for TailSeed<T> {
+    type Value = Option<Box<NonEmptyList<T>>>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_option(self)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for TailSeed<T> {
+    type Value = Option<Box<NonEmptyList<T>>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an optional list")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0
+            .deserialize(deserializer)
+            .map(|list| Some(Box::new(list)))
+    }
+}
+
+impl<T: Copy> NonEmptyList<T> {
+    pub fn to_vec(&self) -> Vec<T> {
+        let mut result = vec![self.head];
+        let mut next = &self.tail;
+        while let Some(list) = next {
+            result.push(list.head);
+            next = &list.tail;
+        }
+        result
+    }
+}
+
+pub fn to_non_empty_list<T>(v: &mut Vec<T>) -> Option<NonEmptyList<T>>
+where
+    T: Copy + Clone,
+{
+    if v.is_empty() {
+        None
+    } else {
+        v.reverse();
+        let mut tail = NonEmptyList {
+            head: *v.first().unwrap(),
+            tail: None,
+        };
+        for i in 1..v.len() {
+            tail = NonEmptyList {
+                head: *v.get(i).unwrap(),
+                tail: Some(Box::new(tail)),
+            };
+        }
+        Some(tail)
+    }
+}
+
+pub mod uuid_as_string {
+    use serde::de::Error;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    pub fn serialize<S>(u: &Uuid, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serde::Serialize::serialize(&u.to_string(), serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s: String = serde::Deserialize::deserialize(deserializer)?;
+        Uuid::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn prop_non_empty_list_yaml(v in proptest::collection::vec(any::<u8>(), 1..50)) {
+            let list = to_non_empty_list(&mut v.clone()).unwrap();
+            let yaml = serde_yaml::to_string(&list).unwrap();
+            let back: NonEmptyList<u8> = serde_yaml::from_str(&yaml).unwrap();
+            prop_assert_eq!(back.to_vec(), v);
+        }
+    }
+
+    #[test]
+    fn test_to_non_empty() {
+        assert_eq!(to_non_empty_list::<u8>(&mut vec![]), None);
+        assert_eq!(
+            to_non_empty_list(&mut vec!['a']),
+            Some(NonEmptyList {
+                head: 'a',
+                tail: None
+            })
+        );
+        assert_eq!(
+            to_non_empty_list(&mut vec![1, 2, 3]),
+            Some(NonEmptyList {
+                head: 1,
+                tail: Some(Box::new(NonEmptyList {
+                    head: 2,
+                    tail: Some(Box::new(NonEmptyList {
+                        head: 3,
+                        tail: None
+                    }))
+                }))
+            })
+        );
+    }
+
+    #[test]
+    fn test_to_vec() {
+        let list = to_non_empty_list(&mut vec![1, 2, 3]).unwrap();
+        assert_eq!(list.to_vec(), vec![1, 2, 3]);
+        let yaml = serde_yaml::to_string(&NestedHints {
+            coords: to_non_empty_list(&mut vec![Coord { col: 1, row: 2 }]),
+        })
+        .unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<NestedHints>(&yaml)
+                .unwrap()
+                .coords
+                .unwrap()
+                .to_vec(),
+            vec![Coord { col: 1, row: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_list_depth() {
+        for format in WireFormat::ALL {
+            let mut cells: Vec<u8> = vec![7; MAX_LIST_DEPTH];
+            let bytes = format.encode(&to_non_empty_list(&mut cells)).unwrap();
+            let list = format.decode::<NonEmptyList<u8>>(&bytes).unwrap();
+            assert_eq!(list.to_vec().len(), MAX_LIST_DEPTH);
+
+            let mut cells: Vec<u8> = vec![7; MAX_LIST_DEPTH + 1];
+            let bytes = format.encode(&to_non_empty_list(&mut cells)).unwrap();
+            assert!(format.decode::<NonEmptyList<u8>>(&bytes).is_err());
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::uuid_as_string;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use crate::{Check, Coord, Hints};
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[schemars(rename = "NewGameV2")]
+pub struct NewGame {
+    #[serde(with = "uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub game_setup_id: Uuid,
+    #[serde(with = "uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub session_id: Uuid,
+    pub number_of_hints: u8,
+    pub occupied_rows: [u8; 10],
+    pub occupied_cols: [u8; 10],
+    // how hard the puzzle is to deduce from the advertised hints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct CheckResult {
+    pub solved: bool,
+    pub correct: usize,
+    pub wrong: usize,
+    pub missing: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Error {
+    pub error: String,
+}
@@ -0,0 +1,217 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+    Cbor,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+}
+
+#[derive(Debug)]
+pub struct WireError(pub(crate) String);
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for WireError {}
+
+fn wire_error(e: impl fmt::Display) -> WireError {
+    WireError(e.to_string())
+}
+
+// The same limit serde_json applies, CBOR and MessagePack are held to it too.
+pub const MAX_NESTING: usize = 128;
+
+// toml parses inline tables and arrays recursively before deserializing and
+// has no limit of its own. Brackets inside strings count too, which only errs
+// on the safe side.
+pub fn check_nesting(bytes: &[u8]) -> Result<(), WireError> {
+    let mut depth: usize = 0;
+    for b in bytes {
+        match b {
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if depth > MAX_NESTING {
+            return Err(WireError(format!("nested deeper than {}", MAX_NESTING)));
+        }
+    }
+    Ok(())
+}
+
+impl WireFormat {
+    pub const ALL: [WireFormat; 5] = [
+        WireFormat::Yaml,
+        WireFormat::Json,
+        WireFormat::Toml,
+        WireFormat::Cbor,
+        WireFormat::MessagePack,
+    ];
+
+    // nested is the content type of the NonEmptyList variants
+    pub fn content_type(self, nested: bool) -> &'static str {
+        match (self, nested) {
+            (WireFormat::Yaml, false) => "text/x-yaml",
+            (WireFormat::Yaml, true) => "text/x-yaml-nested-lists",
+            (WireFormat::Json, false) => "application/json",
+            (WireFormat::Json, true) => "application/x-json-nested-lists",
+            (WireFormat::Toml, false) => "application/toml",
+            (WireFormat::Toml, true) => "application/x-toml-nested-lists",
+            (WireFormat::Cbor, false) => "application/cbor",
+            (WireFormat::Cbor, true) => "application/x-cbor-nested-lists",
+            (WireFormat::MessagePack, false) => "application/msgpack",
+            (WireFormat::MessagePack, true) => "application/x-msgpack-nested-lists",
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<(WireFormat, bool)> {
+        WireFormat::ALL.iter().find_map(|format| {
+            [false, true]
+                .into_iter()
+                .find(|nested| format.content_type(*nested) == content_type)
+                .map(|nested| (*format, nested))
+        })
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, WireError> {
+        match self {
+            WireFormat::Yaml => serde_yaml::to_string(value)
+                .map(String::into_bytes)
+                .map_err(wire_error),
+            WireFormat::Json => serde_json::to_vec(value).map_err(wire_error),
+            WireFormat::Toml => toml::to_vec(value).map_err(wire_error),
+            WireFormat::Cbor => {
+                let mut bytes = vec![];
+                ciborium::ser::into_writer(value, &mut bytes).map_err(wire_error)?;
+                Ok(bytes)
+            }
+            WireFormat::MessagePack => rmp_serde::to_vec_named(value).map_err(wire_error),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, WireError> {
+        match self {
+            WireFormat::Yaml => serde_yaml::from_slice(bytes).map_err(wire_error),
+            WireFormat::Json => serde_json::from_slice(bytes).map_err(wire_error),
+            WireFormat::Toml => {
+                check_nesting(bytes)?;
+                toml::from_slice(bytes).map_err(wire_error)
+            }
+            WireFormat::Cbor => ciborium::de::from_reader_with_recursion_limit(bytes, MAX_NESTING)
+                .map_err(wire_error),
+            WireFormat::MessagePack => {
+                // the default depth of 1024 overflows the stack of a debug build
+                // skipping an unknown field
+                let mut deserializer = rmp_serde::Deserializer::from_read_ref(bytes);
+                deserializer.set_max_depth(MAX_NESTING);
+                T::deserialize(&mut deserializer).map_err(wire_error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{to_non_empty_list, Check, Coord, NestedHints, NestedNewGame, NewGame};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_round_trips() {
+        let game = NewGame {
+            game_setup_id: Uuid::new_v4(),
+            number_of_hints: 10,
+            occupied_rows: [1, 2, 3, 4, 0, 0, 4, 3, 2, 1],
+            occupied_cols: [0, 1, 2, 3, 4, 4, 3, 2, 1, 0],
+        };
+        let nested = NestedNewGame {
+            game_setup_id: game.game_setup_id,
+            number_of_hints: 10,
+            occupied_rows: to_non_empty_list(&mut game.occupied_rows.to_vec()),
+            occupied_cols: None,
+        };
+        let coords = vec![Coord { col: 1, row: 2 }, Coord { col: 3, row: 4 }];
+        let hints = NestedHints {
+            coords: to_non_empty_list(&mut coords.clone()),
+        };
+        let check = Check { coords };
+        for format in WireFormat::ALL {
+            let bytes = format.encode(&game).unwrap();
+            assert_eq!(format.decode::<NewGame>(&bytes).unwrap(), game);
+            let bytes = format.encode(&nested).unwrap();
+            assert_eq!(format.decode::<NestedNewGame>(&bytes).unwrap(), nested);
+            let bytes = format.encode(&hints).unwrap();
+            assert_eq!(format.decode::<NestedHints>(&bytes).unwrap(), hints);
+            let bytes = format.encode(&check).unwrap();
+            assert_eq!(format.decode::<Check>(&bytes).unwrap(), check);
+            assert!(format.decode::<Check>(b"\xff").is_err());
+        }
+    }
+
+    // deeply nested hostile bodies are errors rather than stack overflows
+    #[test]
+    fn test_deep_nesting() {
+        let depth = 1_000;
+        let text = |prefix: &str, open: &str, close: &str| {
+            let mut body = prefix.to_string();
+            body.push_str(&open.repeat(depth));
+            body.push_str(&close.repeat(depth));
+            body.into_bytes()
+        };
+        let mut cbor = vec![0xa1, 0x66];
+        cbor.extend_from_slice(b"coords");
+        let mut msgpack = vec![0x81, 0xa6];
+        msgpack.extend_from_slice(b"coords");
+        for _ in 0..depth {
+            cbor.extend_from_slice(&[0xa1, 0x64]);
+            cbor.extend_from_slice(b"tail");
+            msgpack.extend_from_slice(&[0x81, 0xa4]);
+            msgpack.extend_from_slice(b"tail");
+        }
+        let bodies = [
+            (WireFormat::Yaml, text("coords: ", "{tail: ", "}")),
+            (WireFormat::Json, text("{\"coords\": ", "{\"tail\": ", "}}")),
+            (WireFormat::Toml, text("coords = ", "{tail = ", "}")),
+            (WireFormat::Cbor, cbor),
+            (WireFormat::MessagePack, msgpack),
+        ];
+        for (format, body) in bodies {
+            assert!(format.decode::<NestedHints>(&body).is_err(), "{:?}", format);
+        }
+
+        // nested arrays in a field nobody reads
+        let mut cbor = vec![0xa1, 0x61, b'x'];
+        cbor.extend(vec![0x81; depth * 10]);
+        cbor.push(0x00);
+        let mut msgpack = vec![0x81, 0xa1, b'x'];
+        msgpack.extend(vec![0x91; depth * 10]);
+        msgpack.push(0x00);
+        for (format, body) in [(WireFormat::Cbor, cbor), (WireFormat::MessagePack, msgpack)] {
+            assert!(format.decode::<Check>(&body).is_err(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_content_types() {
+        for format in WireFormat::ALL {
+            for nested in [false, true] {
+                assert_eq!(
+                    WireFormat::from_content_type(format.content_type(nested)),
+                    Some((format, nested))
+                );
+            }
+        }
+        assert_eq!(WireFormat::from_content_type("text/plain"), None);
+    }
+}
@@ -0,0 +1,16 @@
+use crate::{State, CT_PLAIN};
+use tide::{Request, Response};
+
+pub fn is_admin(req: &Request<State>) -> bool {
+    match (&req.state().admin_token, req.header("Authorization")) {
+        (Some(token), Some(value)) => value.last().as_str() == format!("Bearer {}", token),
+        _ => false,
+    }
+}
+
+pub fn forbidden() -> tide::Result {
+    Ok(Response::builder(403)
+        .body("Admin token required")
+        .content_type(CT_PLAIN)
+        .build())
+}
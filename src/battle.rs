@@ -0,0 +1,465 @@
+use crate::setups::{from_cells, ship_of, Setup, FLEET};
+use crate::{conflict, not_found, unprocessable, yaml_response, Coord, State, CT_YAML};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tide::Request;
+use uuid::Uuid;
+
+pub const PLAYERS: usize = 2;
+
+// How long a finished battle can still be looked up before it is dropped.
+pub const FINISHED_TTL: Duration = Duration::from_secs(10 * 60);
+// How long an unfinished battle can go without a player joining or firing.
+pub const IDLE_TTL: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Clone)]
+struct Player {
+    id: Uuid,
+    name: String,
+    fleet: Setup,
+    shots_taken: [[bool; 10]; 10],
+}
+
+#[derive(Clone)]
+pub struct Battle {
+    players: Vec<Player>,
+    turn: usize,
+    winner: Option<usize>,
+    finished_at: Option<Instant>,
+    active_at: Instant,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ShotResult {
+    Miss,
+    Hit,
+    Sunk,
+    Won,
+}
+
+#[derive(Debug)]
+pub enum ShotError {
+    UnknownPlayer,
+    NotStarted,
+    Finished,
+    NotYourTurn,
+    OutOfBounds,
+    AlreadyShot,
+}
+
+impl Player {
+    fn ship_sunk(&self, ship: usize) -> bool {
+        for i in 0..10 {
+            for j in 0..10 {
+                if ship_of(self.fleet[i][j]) == Some(ship) && !self.shots_taken[i][j] {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn fleet_sunk(&self) -> bool {
+        (0..FLEET.len()).all(|ship| self.ship_sunk(ship))
+    }
+}
+
+impl Battle {
+    pub fn new() -> Battle {
+        Battle {
+            players: vec![],
+            turn: 0,
+            winner: None,
+            finished_at: None,
+            active_at: Instant::now(),
+        }
+    }
+
+    pub fn register(&mut self, name: String, fleet: Setup) -> Option<Uuid> {
+        if self.players.len() >= PLAYERS {
+            return None;
+        }
+        let id = Uuid::new_v4();
+        self.players.push(Player {
+            id,
+            name,
+            fleet,
+            shots_taken: [[false; 10]; 10],
+        });
+        self.active_at = Instant::now();
+        Some(id)
+    }
+
+    pub fn fire(&mut self, player_id: &Uuid, target: Coord) -> Result<ShotResult, ShotError> {
+        let shooter = self
+            .players
+            .iter()
+            .position(|p| p.id == *player_id)
+            .ok_or(ShotError::UnknownPlayer)?;
+        if self.players.len() < PLAYERS {
+            return Err(ShotError::NotStarted);
+        }
+        if self.winner.is_some() {
+            return Err(ShotError::Finished);
+        }
+        if shooter != self.turn {
+            return Err(ShotError::NotYourTurn);
+        }
+        if target.row > 9 || target.col > 9 {
+            return Err(ShotError::OutOfBounds);
+        }
+        let opponent = &mut self.players[(shooter + 1) % PLAYERS];
+        if opponent.shots_taken[target.row][target.col] {
+            return Err(ShotError::AlreadyShot);
+        }
+        opponent.shots_taken[target.row][target.col] = true;
+        self.active_at = Instant::now();
+        let result = match ship_of(opponent.fleet[target.row][target.col]) {
+            None => ShotResult::Miss,
+            Some(_) if opponent.fleet_sunk() => ShotResult::Won,
+            Some(ship) if opponent.ship_sunk(ship) => ShotResult::Sunk,
+            Some(_) => ShotResult::Hit,
+        };
+        if result == ShotResult::Won {
+            self.winner = Some(shooter);
+            self.finished_at = Some(Instant::now());
+        } else {
+            self.turn = (shooter + 1) % PLAYERS;
+        }
+        Ok(result)
+    }
+
+    fn status(&self, battle_id: Uuid) -> BattleStatus {
+        BattleStatus {
+            battle_id,
+            players: self.players.iter().map(|p| p.name.clone()).collect(),
+            turn: if self.players.len() == PLAYERS && self.winner.is_none() {
+                Some(self.players[self.turn].name.clone())
+            } else {
+                None
+            },
+            winner: self.winner.map(|w| self.players[w].name.clone()),
+        }
+    }
+}
+
+// Battles finished longer than FINISHED_TTL ago are dropped, and so are
+// battles left idle for IDLE_TTL before they finished.
+fn evict(battles: &mut im::HashMap<Uuid, Battle>, now: Instant) {
+    battles.retain(|_, battle| match battle.finished_at {
+        Some(at) => now.duration_since(at) < FINISHED_TTL,
+        None => now.duration_since(battle.active_at) < IDLE_TTL,
+    });
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct BattleStatus {
+    #[serde(with = "crate::uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub battle_id: Uuid,
+    pub players: Vec<String>,
+    pub turn: Option<String>,
+    pub winner: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Registration {
+    pub name: String,
+    pub coords: Vec<Coord>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Registered {
+    #[serde(with = "crate::uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub player_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Shot {
+    #[serde(with = "crate::uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub player_id: Uuid,
+    pub coord: Coord,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ShotOutcome {
+    pub result: ShotResult,
+}
+
+pub async fn create(req: Request<State>) -> tide::Result {
+    let battle_id = Uuid::new_v4();
+    let battle = Battle::new();
+    let status = battle.status(battle_id);
+    let mut battles = req.state().battles.lock().unwrap();
+    evict(&mut battles, Instant::now());
+    battles.insert(battle_id, battle);
+    yaml_response(201, CT_YAML, &status)
+}
+
+pub async fn show(req: Request<State>) -> tide::Result {
+    let battle_id_str: &str = req.param("battle_id")?;
+    if let Ok(battle_id) = Uuid::from_str(battle_id_str) {
+        if let Some(battle) = req.state().battles.lock().unwrap().get(&battle_id) {
+            yaml_response(200, CT_YAML, &battle.status(battle_id))
+        } else {
+            not_found("Unknown battle")
+        }
+    } else {
+        not_found("Battle id not found")
+    }
+}
+
+pub async fn register(mut req: Request<State>) -> tide::Result {
+    let battle_id_str: &str = req.param("battle_id")?;
+    if let Ok(battle_id) = Uuid::from_str(battle_id_str) {
+        let body_str = req.body_string().await?;
+        if let Ok(entity) = serde_yaml::from_str::<Registration>(&body_str) {
+            let cells: Vec<(usize, usize)> = entity.coords.iter().map(|c| (c.row, c.col)).collect();
+            if let Some(fleet) = from_cells(&cells) {
+                if let Some(battle) = req.state().battles.lock().unwrap().get_mut(&battle_id) {
+                    if let Some(player_id) = battle.register(entity.name, fleet) {
+                        yaml_response(201, CT_YAML, &Registered { player_id })
+                    } else {
+                        conflict("Battle already has two players")
+                    }
+                } else {
+                    not_found("Unknown battle")
+                }
+            } else {
+                unprocessable("Fleet is not legal")
+            }
+        } else {
+            crate::illegal_request("Could not parse entity")
+        }
+    } else {
+        not_found("Battle id not found")
+    }
+}
+
+pub async fn fire(mut req: Request<State>) -> tide::Result {
+    let battle_id_str: &str = req.param("battle_id")?;
+    if let Ok(battle_id) = Uuid::from_str(battle_id_str) {
+        let body_str = req.body_string().await?;
+        if let Ok(entity) = serde_yaml::from_str::<Shot>(&body_str) {
+            if let Some(battle) = req.state().battles.lock().unwrap().get_mut(&battle_id) {
+                match battle.fire(&entity.player_id, entity.coord) {
+                    Ok(result) => yaml_response(200, CT_YAML, &ShotOutcome { result }),
+                    Err(ShotError::UnknownPlayer) => not_found("Unknown player"),
+                    Err(ShotError::NotStarted) => conflict("Waiting for the second player"),
+                    Err(ShotError::Finished) => conflict("Battle is over"),
+                    Err(ShotError::NotYourTurn) => conflict("Not your turn"),
+                    Err(ShotError::OutOfBounds) => unprocessable("Coordinate is off the board"),
+                    Err(ShotError::AlreadyShot) => conflict("Cell was already shot"),
+                }
+            } else {
+                not_found("Unknown battle")
+            }
+        } else {
+            crate::illegal_request("Could not parse entity")
+        }
+    } else {
+        not_found("Battle id not found")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_app;
+    use crate::setups::{cells, GAME_1, GAME_2};
+    use tide_testing::TideTestingExt;
+
+    fn registration(name: &str, setup: &Setup) -> String {
+        let coords = cells(setup)
+            .into_iter()
+            .map(|(row, col)| Coord { row, col })
+            .collect();
+        serde_yaml::to_string(&Registration {
+            name: name.to_string(),
+            coords,
+        })
+        .unwrap()
+    }
+
+    #[async_std::test]
+    async fn test_bots_play_to_the_end() {
+        let app = build_app();
+        let created = app.post("/battles").recv_string().await.unwrap();
+        let battle_id = serde_yaml::from_str::<BattleStatus>(&created)
+            .unwrap()
+            .battle_id;
+
+        let mut bots = vec![];
+        for (name, setup) in [("left", GAME_1), ("right", GAME_2)] {
+            let resp = app
+                .post(format!("/battles/{}/players", battle_id))
+                .body_string(registration(name, &setup))
+                .recv_string()
+                .await
+                .unwrap();
+            bots.push(serde_yaml::from_str::<Registered>(&resp).unwrap().player_id);
+        }
+        let full = app
+            .post(format!("/battles/{}/players", battle_id))
+            .body_string(registration("late", &GAME_1))
+            .await
+            .unwrap();
+        assert_eq!(full.status(), 409);
+
+        // both bots scan the board row by row, so the first player wins
+        let mut sunk = 0;
+        let mut last = ShotResult::Miss;
+        'game: for row in 0..10 {
+            for col in 0..10 {
+                for player_id in bots.iter() {
+                    let shot = Shot {
+                        player_id: *player_id,
+                        coord: Coord { row, col },
+                    };
+                    let resp = app
+                        .post(format!("/battles/{}/shots", battle_id))
+                        .body_string(serde_yaml::to_string(&shot).unwrap())
+                        .recv_string()
+                        .await
+                        .unwrap();
+                    last = serde_yaml::from_str::<ShotOutcome>(&resp).unwrap().result;
+                    if last == ShotResult::Sunk && *player_id == bots[0] {
+                        sunk += 1;
+                    }
+                    if last == ShotResult::Won {
+                        break 'game;
+                    }
+                }
+            }
+        }
+        assert_eq!(last, ShotResult::Won);
+        assert_eq!(sunk, FLEET.len() - 1);
+
+        let status_resp = app
+            .get(format!("/battles/{}", battle_id))
+            .recv_string()
+            .await
+            .unwrap();
+        let status = serde_yaml::from_str::<BattleStatus>(&status_resp).unwrap();
+        assert_eq!(status.winner, Some("left".to_string()));
+        assert_eq!(status.turn, None);
+    }
+
+    #[async_std::test]
+    async fn test_illegal_fleet() {
+        let app = build_app();
+        let created = app.post("/battles").recv_string().await.unwrap();
+        let battle_id = serde_yaml::from_str::<BattleStatus>(&created)
+            .unwrap()
+            .battle_id;
+        let mut touching = GAME_1;
+        touching[1][0] = 0;
+        touching[1][4] = 17;
+        let resp = app
+            .post(format!("/battles/{}/players", battle_id))
+            .body_string(registration("cheater", &touching))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 422);
+    }
+
+    #[async_std::test]
+    async fn test_shoot_twice() {
+        let app = build_app();
+        let created = app.post("/battles").recv_string().await.unwrap();
+        let battle_id = serde_yaml::from_str::<BattleStatus>(&created)
+            .unwrap()
+            .battle_id;
+        let mut bots = vec![];
+        for (name, setup) in [("left", GAME_1), ("right", GAME_2)] {
+            let resp = app
+                .post(format!("/battles/{}/players", battle_id))
+                .body_string(registration(name, &setup))
+                .recv_string()
+                .await
+                .unwrap();
+            bots.push(serde_yaml::from_str::<Registered>(&resp).unwrap().player_id);
+        }
+        let origin = Coord { row: 0, col: 0 };
+        let mut statuses = vec![];
+        for player_id in [bots[0], bots[1], bots[0]] {
+            let shot = Shot {
+                player_id,
+                coord: origin,
+            };
+            let resp = app
+                .post(format!("/battles/{}/shots", battle_id))
+                .body_string(serde_yaml::to_string(&shot).unwrap())
+                .await
+                .unwrap();
+            statuses.push(resp.status());
+        }
+        assert_eq!(statuses, [200, 200, 409]);
+    }
+
+    #[test]
+    fn test_turns() {
+        let mut battle = Battle::new();
+        let left = battle.register("left".to_string(), GAME_1).unwrap();
+        let origin = Coord { row: 0, col: 0 };
+        assert!(matches!(
+            battle.fire(&left, origin),
+            Err(ShotError::NotStarted)
+        ));
+        let right = battle.register("right".to_string(), GAME_2).unwrap();
+        assert!(matches!(
+            battle.fire(&right, origin),
+            Err(ShotError::NotYourTurn)
+        ));
+        assert_eq!(
+            battle.fire(&left, Coord { row: 0, col: 8 }).unwrap(),
+            ShotResult::Sunk
+        );
+        assert_eq!(
+            battle.fire(&right, Coord { row: 0, col: 8 }).unwrap(),
+            ShotResult::Hit
+        );
+        assert!(matches!(
+            battle.fire(&left, Coord { row: 0, col: 8 }),
+            Err(ShotError::AlreadyShot)
+        ));
+    }
+
+    #[test]
+    fn test_evict() {
+        let now = Instant::now();
+        let mut battles = im::HashMap::new();
+        let open = Uuid::new_v4();
+        let finished = Uuid::new_v4();
+        let busy = Uuid::new_v4();
+        battles.insert(open, Battle::new());
+        battles.insert(
+            finished,
+            Battle {
+                finished_at: Some(now),
+                ..Battle::new()
+            },
+        );
+        battles.insert(
+            busy,
+            Battle {
+                active_at: now + FINISHED_TTL,
+                ..Battle::new()
+            },
+        );
+        evict(&mut battles, now);
+        assert_eq!(battles.len(), 3);
+        evict(&mut battles, now + FINISHED_TTL);
+        assert!(battles.contains_key(&open));
+        assert!(!battles.contains_key(&finished));
+        // an unfinished battle goes once nobody joined or fired for IDLE_TTL
+        evict(&mut battles, now + IDLE_TTL + Duration::from_secs(1));
+        assert!(!battles.contains_key(&open));
+        assert!(battles.contains_key(&busy));
+    }
+}
@@ -0,0 +1,233 @@
+use bimaru::search::{irreducible_hints, solve, Known, Puzzle};
+use bimaru::setups::{
+    build_all, cells, load_catalog, to_catalog, OccupiedCells, Reveals, Setup, SetupFormat, Setups,
+};
+use bimaru::{hints_for, render_board, Check, IsSolved, MAX_HINTS};
+use std::io::{self, BufRead, Write};
+use std::process::exit;
+use std::str::FromStr;
+use uuid::Uuid;
+
+const USAGE: &str = "Usage: bimaru-cli [--catalog FILE] <command>
+
+Commands:
+  list                          list setup ids
+  catalog                       print the catalog as YAML
+  minimize                      print the catalog with irreducible hint sets for a unique solution
+  show <setup_id>               print the puzzle with row and column counts
+  solve <setup_id> [--hints N]  solve the puzzle from its counts and N hints
+  play <setup_id>               play the puzzle in the terminal
+  verify <setup_id> <FILE>      check a solution file in the setup's wire format";
+
+const PLAY_HELP: &str = "Commands:
+  <row> <col>    toggle a ship on the cell
+  w <row> <col>  toggle water on the cell
+  hint           reveal the next hint
+  check          check the board
+  quit           leave the game";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    exit(2)
+}
+
+// Decorates the render_board output with water marks and the counts.
+fn render_puzzle(
+    ships: &Setup,
+    water: &[[bool; 10]; 10],
+    rows: [u8; 10],
+    cols: [u8; 10],
+) -> String {
+    let mut lines: Vec<String> = vec![];
+    for (i, line) in render_board(ships).lines().enumerate() {
+        if i == 0 {
+            lines.push(line.to_string());
+            continue;
+        }
+        let row = i - 1;
+        let cells: String = line
+            .chars()
+            .enumerate()
+            .map(|(j, ch)| if j > 0 && water[row][j - 1] { '~' } else { ch })
+            .collect();
+        lines.push(format!("{} {}", cells, rows[row]));
+    }
+    lines.push(format!(
+        " {}",
+        cols.iter().map(|c| c.to_string()).collect::<String>()
+    ));
+    lines.join("\n")
+}
+
+fn find_setup(setups: &Setups, id: &str) -> SetupFormat {
+    match Uuid::from_str(id).ok().and_then(|id| setups.get(&id)) {
+        Some(sf) => *sf,
+        None => fail("Unknown game setup"),
+    }
+}
+
+fn show(setup: &Setup) {
+    let empty: Setup = [[0; 10]; 10];
+    println!(
+        "{}",
+        render_puzzle(
+            &empty,
+            &[[false; 10]; 10],
+            setup.occupied_rows(),
+            setup.occupied_cols()
+        )
+    );
+}
+
+fn solve_setup(sf: &SetupFormat, hints: u8) {
+    let setup = &sf.setup;
+    let mut puzzle = Puzzle::from_setup(setup);
+    for c in hints_for(sf, hints) {
+        puzzle.reveal(c.row, c.col, Known::Ship);
+    }
+    match solve(&puzzle) {
+        Some(solution) => {
+            println!("{}", render_board(&solution));
+            if Check::from_cells(&cells(&solution)).solves(*setup) {
+                println!("Matches the setup");
+            } else {
+                println!("Differs from the setup, try more hints");
+            }
+        }
+        None => fail("No solution"),
+    }
+}
+
+fn play(sf: &SetupFormat) {
+    let setup = &sf.setup;
+    let mut ships: Setup = [[0; 10]; 10];
+    let mut water = [[false; 10]; 10];
+    let mut hints: u8 = 0;
+    let stdin = io::stdin();
+    println!("{}", PLAY_HELP);
+    loop {
+        println!(
+            "{}",
+            render_puzzle(&ships, &water, setup.occupied_rows(), setup.occupied_cols())
+        );
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let cell = |r: &str, c: &str| match (r.parse::<usize>(), c.parse::<usize>()) {
+            (Ok(r), Ok(c)) if r < 10 && c < 10 => Some((r, c)),
+            _ => None,
+        };
+        match words.as_slice() {
+            ["quit"] => return,
+            ["hint"] => {
+                hints = (hints + 1).min(MAX_HINTS);
+                for c in hints_for(sf, hints) {
+                    ships[c.row][c.col] = 1;
+                    water[c.row][c.col] = false;
+                }
+            }
+            ["check"] => {
+                if Check::from_cells(&cells(&ships)).solves(*setup) {
+                    println!("Well done!");
+                    return;
+                }
+                println!("Try harder!");
+            }
+            ["w", r, c] => match cell(r, c) {
+                Some((r, c)) => {
+                    water[r][c] = !water[r][c];
+                    ships[r][c] = 0;
+                }
+                None => println!("{}", PLAY_HELP),
+            },
+            [r, c] => match cell(r, c) {
+                Some((r, c)) => {
+                    ships[r][c] = if ships[r][c] > 0 { 0 } else { 1 };
+                    water[r][c] = false;
+                }
+                None => println!("{}", PLAY_HELP),
+            },
+            _ => println!("{}", PLAY_HELP),
+        }
+    }
+}
+
+fn verify(sf: &SetupFormat, path: &str) {
+    let content = std::fs::read(path).unwrap_or_else(|e| fail(&e.to_string()));
+    match sf.wire_format.decode::<Check>(&content) {
+        Ok(check) if check.solves(sf.setup) => println!("Well done!"),
+        Ok(_) => {
+            println!("Try harder!");
+            exit(1)
+        }
+        Err(e) => fail(&e.to_string()),
+    }
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let setups = match args.iter().position(|a| a == "--catalog") {
+        Some(i) if i + 1 < args.len() => {
+            let path = args.remove(i + 1);
+            args.remove(i);
+            load_catalog(&path).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)))
+        }
+        Some(_) => fail(USAGE),
+        None => build_all(),
+    };
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    match args.as_slice() {
+        ["list"] => {
+            for e in to_catalog(&setups) {
+                println!("{}", e.id);
+            }
+        }
+        ["catalog"] => print!("{}", serde_yaml::to_string(&to_catalog(&setups)).unwrap()),
+        ["minimize"] => {
+            let mut entries = to_catalog(&setups);
+            for e in entries.iter_mut().filter(|e| e.irreducible_hints.is_none()) {
+                e.irreducible_hints = Some(Reveals::of(&irreducible_hints(&e.setup)).into());
+            }
+            print!("{}", serde_yaml::to_string(&entries).unwrap())
+        }
+        ["show", id] => show(&find_setup(&setups, id).setup),
+        ["solve", id] => solve_setup(&find_setup(&setups, id), 0),
+        ["solve", id, "--hints", n] => match n.parse::<u8>() {
+            Ok(n) => solve_setup(&find_setup(&setups, id), n.min(MAX_HINTS)),
+            Err(_) => fail(USAGE),
+        },
+        ["play", id] => play(&find_setup(&setups, id)),
+        ["verify", id, path] => verify(&find_setup(&setups, id), path),
+        _ => fail(USAGE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_puzzle() {
+        let mut ships: Setup = [[0; 10]; 10];
+        ships[0][0] = 1;
+        let mut water = [[false; 10]; 10];
+        water[0][1] = true;
+        water[9][9] = true;
+        let mut rows = [0; 10];
+        rows[0] = 1;
+        let mut cols = [0; 10];
+        cols[0] = 1;
+        let rendered = render_puzzle(&ships, &water, rows, cols);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 12);
+        assert_eq!(lines[0], " 0123456789");
+        assert_eq!(lines[1], "0#~         1");
+        assert_eq!(lines[2], "1           0");
+        assert_eq!(lines[10], "9         ~ 0");
+        assert_eq!(lines[11], " 1000000000");
+    }
+}
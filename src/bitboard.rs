@@ -0,0 +1,270 @@
+use crate::setups::{Setup, FLEET, MAX_INDEX};
+
+// bit row * 10 + col of the 100 cells
+pub const ALL: u128 = (1 << 100) - 1;
+pub const ROW_0: u128 = (1 << 10) - 1;
+pub const COL_0: u128 = {
+    let mut mask = 0;
+    let mut r = 0;
+    while r < 10 {
+        mask |= 1 << (r * 10);
+        r += 1;
+    }
+    mask
+};
+const COL_9: u128 = COL_0 << 9;
+
+pub fn bit(row: usize, col: usize) -> u128 {
+    1 << (row * 10 + col)
+}
+
+pub fn cells_of(mask: u128) -> Vec<(usize, usize)> {
+    let mut result = vec![];
+    let mut rest = mask;
+    while rest != 0 {
+        let i = rest.trailing_zeros() as usize;
+        result.push((i / 10, i % 10));
+        rest &= rest - 1;
+    }
+    result
+}
+
+pub fn of_cells(cells: &[(usize, usize)]) -> Option<u128> {
+    let mut mask = 0;
+    for &(r, c) in cells {
+        if r > MAX_INDEX || c > MAX_INDEX || mask & bit(r, c) != 0 {
+            return None;
+        }
+        mask |= bit(r, c);
+    }
+    Some(mask)
+}
+
+// The cells sharing an edge with the mask, the mask itself included.
+fn grow(mask: u128) -> u128 {
+    (mask | (mask & !COL_9) << 1 | (mask & !COL_0) >> 1 | mask << 10 | mask >> 10) & ALL
+}
+
+// The cells sharing an edge or a corner with the mask, the mask itself included.
+pub fn halo(mask: u128) -> u128 {
+    let wide = mask | (mask & !COL_9) << 1 | (mask & !COL_0) >> 1;
+    (wide | wide << 10 | wide >> 10) & ALL
+}
+
+fn is_straight(ship: u128) -> bool {
+    let first = ship.trailing_zeros();
+    let size = ship.count_ones();
+    let row = (ROW_0 >> (10 - size)) << first;
+    let col = cells_of(ship).iter().all(|&(_, c)| c as u32 == first % 10);
+    (row == ship && first % 10 + size <= 10) || col
+}
+
+// The ships of an occupancy mask, largest first and ordered by their first
+// cell, when they make up the fleet.
+pub fn fleet(occupied: u128) -> Option<[u128; 10]> {
+    let mut ships: Vec<u128> = vec![];
+    let mut rest = occupied & ALL;
+    while rest != 0 {
+        let mut ship = rest & rest.wrapping_neg();
+        loop {
+            let next = grow(ship) & occupied;
+            if next == ship {
+                break;
+            }
+            ship = next;
+        }
+        // longer than any ship of the fleet, and too long for is_straight
+        if ship.count_ones() as usize > FLEET[0] || !is_straight(ship) {
+            return None;
+        }
+        ships.push(ship);
+        rest &= !ship;
+    }
+    ships.sort_by(|a, b| {
+        b.count_ones()
+            .cmp(&a.count_ones())
+            .then(a.trailing_zeros().cmp(&b.trailing_zeros()))
+    });
+    let sizes: Vec<usize> = ships.iter().map(|s| s.count_ones() as usize).collect();
+    if sizes != FLEET {
+        return None;
+    }
+    let mut result = [0; 10];
+    result.copy_from_slice(&ships);
+    Some(result)
+}
+
+// where a ship starts and how its numbering runs: +1/-1 along a row, +10/-10
+// down or up a column
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ShipMeta {
+    pub head: u8,
+    pub step: i8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Bitboard {
+    pub occupied: u128,
+    pub ships: [ShipMeta; 10],
+}
+
+impl Bitboard {
+    // None unless every value from 1 to 20 is there once and the cells of each
+    // ship follow each other in a line.
+    pub fn from_setup(setup: &Setup) -> Option<Bitboard> {
+        let mut position = [None; 21];
+        for (r, row) in setup.iter().enumerate() {
+            for (c, value) in row.iter().enumerate() {
+                let value = *value as usize;
+                if value > 20 || (value > 0 && position[value].is_some()) {
+                    return None;
+                }
+                if value > 0 {
+                    position[value] = Some((r * 10 + c) as i32);
+                }
+            }
+        }
+        let mut occupied = 0;
+        let mut ships = [ShipMeta::default(); 10];
+        let mut value = 1;
+        for (ship, size) in FLEET.iter().enumerate() {
+            let head = position[value]?;
+            let step = if *size > 1 {
+                position[value + 1]? - head
+            } else {
+                1
+            };
+            if ![1, -1, 10, -10].contains(&step) {
+                return None;
+            }
+            for k in 0..*size as i32 {
+                let at = position[value + k as usize]?;
+                // a row step must not wrap into the next row
+                if at != head + k * step || (step.abs() == 1 && at / 10 != head / 10) {
+                    return None;
+                }
+                occupied |= 1 << at;
+            }
+            ships[ship] = ShipMeta {
+                head: head as u8,
+                step: step as i8,
+            };
+            value += size;
+        }
+        Some(Bitboard { occupied, ships })
+    }
+
+    pub fn to_setup(&self) -> Setup {
+        let mut result: Setup = [[0; 10]; 10];
+        let mut value = 1;
+        for (meta, size) in self.ships.iter().zip(FLEET) {
+            for k in 0..size as i32 {
+                let at = (meta.head as i32 + k * meta.step as i32) as usize;
+                result[at / 10][at % 10] = value;
+                value += 1;
+            }
+        }
+        result
+    }
+
+    pub fn ship(&self, n: usize) -> u128 {
+        let meta = self.ships[n];
+        (0..FLEET[n] as i32).fold(0, |mask, k| {
+            mask | 1 << (meta.head as i32 + k * meta.step as i32)
+        })
+    }
+
+    pub fn occupied_rows(&self) -> [u8; 10] {
+        let mut result = [0; 10];
+        for (r, count) in result.iter_mut().enumerate() {
+            *count = ((self.occupied >> (r * 10)) & ROW_0).count_ones() as u8;
+        }
+        result
+    }
+
+    pub fn occupied_cols(&self) -> [u8; 10] {
+        let mut result = [0; 10];
+        for (c, count) in result.iter_mut().enumerate() {
+            *count = (self.occupied & (COL_0 << c)).count_ones() as u8;
+        }
+        result
+    }
+
+    // two ships meet at a corner or an edge
+    pub fn touches(&self) -> bool {
+        (0..FLEET.len()).any(|n| {
+            let ship = self.ship(n);
+            halo(ship) & self.occupied & !ship != 0
+        })
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.touches()
+    }
+
+    // the same cells as IsSolved::solves asks for
+    pub fn solves(&self, occupied: u128) -> bool {
+        occupied == self.occupied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setups::{build_all, cells, from_cells, is_valid, OccupiedCells, GAME_1};
+
+    #[test]
+    fn test_round_trip() {
+        for sf in build_all().values() {
+            let board = Bitboard::from_setup(&sf.setup).unwrap();
+            assert_eq!(board.to_setup(), sf.setup);
+            assert_eq!(board.occupied_rows(), sf.setup.occupied_rows());
+            assert_eq!(board.occupied_cols(), sf.setup.occupied_cols());
+            assert_eq!(board.is_valid(), is_valid(&sf.setup));
+            assert_eq!(cells_of(board.occupied), cells(&sf.setup));
+            let ships = fleet(board.occupied).unwrap();
+            assert_eq!(ships.iter().fold(0, |m, s| m | s), board.occupied);
+            assert_eq!(
+                Bitboard::from_setup(&from_cells(&cells(&sf.setup)).unwrap())
+                    .unwrap()
+                    .occupied,
+                board.occupied
+            );
+        }
+    }
+
+    #[test]
+    fn test_broken_setups() {
+        let mut missing = GAME_1;
+        missing[0][5] = 0;
+        assert_eq!(Bitboard::from_setup(&missing), None);
+
+        // the ship numbered 1 to 4 bends at its end
+        let mut bent = GAME_1;
+        bent[0][8] = 0;
+        bent[1][7] = 1;
+        assert_eq!(Bitboard::from_setup(&bent), None);
+
+        // moved next to the ship numbered 5 to 7
+        let mut touching = GAME_1;
+        touching[1][0] = 0;
+        touching[1][4] = 17;
+        let board = Bitboard::from_setup(&touching).unwrap();
+        assert!(board.touches());
+        assert!(!is_valid(&touching));
+
+        assert_eq!(fleet(bit(0, 0) | bit(0, 1) | bit(1, 1)), None);
+        assert_eq!(fleet(0), None);
+        assert_eq!(fleet(ALL), None);
+        assert_eq!(fleet(ROW_0 | bit(1, 0)), None);
+    }
+
+    #[test]
+    fn test_row_wrap() {
+        // 9 and 10 are neighbours in the mask but not on the board
+        let mask = bit(0, 9) | bit(1, 0);
+        assert_eq!(grow(bit(0, 9)) & mask, bit(0, 9));
+        assert!(!is_straight(mask));
+        assert_eq!(halo(bit(0, 9)) & bit(1, 0), 0);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::setups::Setups;
+use crate::storage::now_millis;
+use crate::{game_response, not_found, yaml_response, State, CT_YAML};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tide::Request;
+use uuid::Uuid;
+
+pub const DATE_HEADER: &str = "X-Daily-Date";
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+const DEFAULT_HISTORY: u16 = 7;
+const MAX_HISTORY: u16 = 365;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct HistoryQuery {
+    days: Option<u16>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct DailyEntry {
+    pub date: String,
+    #[serde(with = "crate::uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub game_setup_id: Uuid,
+}
+
+// days since 1970-01-01 in UTC
+pub fn day_of(millis: u64) -> u64 {
+    millis / DAY_MILLIS
+}
+
+// Every setup draws a number seeded by the day and the highest draw wins, so
+// every player sees the same board on the same date and adding or removing
+// other setups leaves the pick of a day alone.
+pub fn setup_of_day(setups: &Setups, day: u64) -> Option<Uuid> {
+    setups
+        .keys()
+        .copied()
+        .max_by_key(|id| Uuid::new_v5(id, &day.to_be_bytes()))
+}
+
+// the proleptic Gregorian calendar date of a day, as YYYY-MM-DD
+pub fn date_of(day: u64) -> String {
+    let z = day as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+// today first, then the days before
+pub fn history(setups: &Setups, today: u64, days: u16) -> Vec<DailyEntry> {
+    (0..days as u64)
+        .take_while(|back| *back <= today)
+        .filter_map(|back| {
+            setup_of_day(setups, today - back).map(|game_setup_id| DailyEntry {
+                date: date_of(today - back),
+                game_setup_id,
+            })
+        })
+        .collect()
+}
+
+pub async fn daily(req: Request<State>) -> tide::Result {
+    let today = day_of(now_millis());
+    if let Some(game_setup_id) = setup_of_day(&req.state().setups, today) {
+        let sf = req.state().setups.get(&game_setup_id).unwrap();
+        let mut resp = game_response(200, game_setup_id, sf)?;
+        resp.insert_header(DATE_HEADER, date_of(today));
+        Ok(resp)
+    } else {
+        not_found("No setups to pick from")
+    }
+}
+
+pub async fn daily_history(req: Request<State>) -> tide::Result {
+    let days = match req.query::<HistoryQuery>() {
+        Ok(q) => q.days.unwrap_or(DEFAULT_HISTORY).min(MAX_HISTORY),
+        Err(_) => DEFAULT_HISTORY,
+    };
+    let today = day_of(now_millis());
+    yaml_response(200, CT_YAML, &history(&req.state().setups, today, days))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_app;
+    use crate::setups::build_all;
+    use tide_testing::TideTestingExt;
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(date_of(0), "1970-01-01");
+        assert_eq!(date_of(59), "1970-03-01");
+        assert_eq!(date_of(11016), "2000-02-29");
+        assert_eq!(date_of(day_of(1_792_368_000_000)), "2026-10-19");
+    }
+
+    #[test]
+    fn test_rotation() {
+        let setups = build_all();
+        let today = 20_000;
+        assert_eq!(setup_of_day(&setups, today), setup_of_day(&setups, today));
+        assert_ne!(
+            setup_of_day(&setups, today),
+            setup_of_day(&setups, today + 1)
+        );
+        assert_eq!(setup_of_day(&Setups::new(), today), None);
+
+        // the pick does not move when the catalog grows or shrinks around it
+        let pick = setup_of_day(&setups, today).unwrap();
+        let mut fewer = setups.clone();
+        fewer.retain(|id, _| *id == pick || id.as_u128() % 2 == 0);
+        assert!(fewer.len() < setups.len());
+        assert_eq!(setup_of_day(&fewer, today), Some(pick));
+
+        let past = history(&setups, today, 3);
+        assert_eq!(past.len(), 3);
+        assert_eq!(past[0].date, date_of(today));
+        assert_eq!(
+            Some(past[2].game_setup_id),
+            setup_of_day(&setups, today - 2)
+        );
+        assert_eq!(history(&setups, 1, 7).len(), 2);
+    }
+
+    #[async_std::test]
+    async fn test_daily() {
+        let app = build_app();
+        let mut resp = app.get("/daily").await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let today = day_of(now_millis());
+        assert_eq!(resp[DATE_HEADER].last().as_str(), date_of(today));
+        let id = setup_of_day(&app.state().setups, today).unwrap();
+        assert!(resp.body_string().await.unwrap().contains(&id.to_string()));
+
+        let past: Vec<DailyEntry> = serde_yaml::from_str(
+            &app.get("/daily/history?days=3")
+                .recv_string()
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(past.len(), 3);
+        assert_eq!(past[0].game_setup_id, id);
+    }
+}
@@ -0,0 +1,861 @@
+use crate::search::{Known, Puzzle};
+use crate::setups::{Setup, SetupFormat, FLEET, MAX_INDEX, MIN_INDEX};
+use crate::validate::PartialSolution;
+use crate::{
+    find_hints, hint_limit, hints_for, illegal_request, not_found, number_of_hints, session_of,
+    text_response, unprocessable, yaml_response, Coord, State, CT_YAML,
+};
+pub use bimaru_protocol::v2::Difficulty;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tide::Request;
+use uuid::Uuid;
+
+// The part of a ship a cell is, once its neighbours tell.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Segment {
+    Any,
+    Single,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    Middle,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cell {
+    Unknown,
+    Water,
+    Ship(Segment),
+}
+
+#[derive(
+    Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Technique {
+    Hints,
+    Saturation,
+    DiagonalWater,
+    FleetExhaustion,
+    LargestShip,
+    // not a deduction: the cells are read from the solution
+    Solution,
+}
+
+// One deduction: the technique, the reason and the cells it settles.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Step {
+    pub technique: Technique,
+    pub reason: String,
+    pub ship: Vec<Coord>,
+    pub water: Vec<Coord>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Board {
+    pub rows: [u8; 10],
+    pub cols: [u8; 10],
+    pub grid: [[Cell; 10]; 10],
+}
+
+fn coords(cells: &[(usize, usize)]) -> Vec<Coord> {
+    cells.iter().map(|&(row, col)| Coord { col, row }).collect()
+}
+
+fn neighbours(r: usize, c: usize, diagonal: bool) -> Vec<(usize, usize)> {
+    let mut result = vec![];
+    for dr in [-1i32, 0, 1] {
+        for dc in [-1i32, 0, 1] {
+            if (dr == 0 && dc == 0) || ((dr != 0 && dc != 0) != diagonal) {
+                continue;
+            }
+            let (nr, nc) = (r as i32 + dr, c as i32 + dc);
+            if (MIN_INDEX as i32..=MAX_INDEX as i32).contains(&nr)
+                && (MIN_INDEX as i32..=MAX_INDEX as i32).contains(&nc)
+            {
+                result.push((nr as usize, nc as usize));
+            }
+        }
+    }
+    result
+}
+
+fn line(horizontal: bool, i: usize) -> Vec<(usize, usize)> {
+    (MIN_INDEX..=MAX_INDEX)
+        .map(|j| if horizontal { (i, j) } else { (j, i) })
+        .collect()
+}
+
+fn segment(row: usize, col: usize, size: usize, horizontal: bool) -> Option<Vec<(usize, usize)>> {
+    let end = if horizontal { col } else { row } + size - 1;
+    if end > MAX_INDEX {
+        None
+    } else if horizontal {
+        Some((col..=end).map(|c| (row, c)).collect())
+    } else {
+        Some((row..=end).map(|r| (r, col)).collect())
+    }
+}
+
+impl Board {
+    pub fn new(rows: [u8; 10], cols: [u8; 10]) -> Board {
+        Board {
+            rows,
+            cols,
+            grid: [[Cell::Unknown; 10]; 10],
+        }
+    }
+
+    pub fn from_puzzle(puzzle: &Puzzle) -> Board {
+        let mut board = Board::new(puzzle.rows, puzzle.cols);
+        for r in MIN_INDEX..=MAX_INDEX {
+            for c in MIN_INDEX..=MAX_INDEX {
+                board.grid[r][c] = match puzzle.known[r][c] {
+                    Some(Known::Ship) => Cell::Ship(Segment::Any),
+                    Some(Known::Water) => Cell::Water,
+                    None => Cell::Unknown,
+                };
+            }
+        }
+        board.refine();
+        board
+    }
+
+    fn at(&self, (r, c): (usize, usize)) -> Cell {
+        self.grid[r][c]
+    }
+
+    fn is_ship(&self, rc: (usize, usize)) -> bool {
+        matches!(self.at(rc), Cell::Ship(_))
+    }
+
+    // water or off the board
+    fn is_closed(&self, r: i32, c: i32) -> bool {
+        !(0..10).contains(&r)
+            || !(0..10).contains(&c)
+            || self.grid[r as usize][c as usize] == Cell::Water
+    }
+
+    fn unknown(&self, cells: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        cells
+            .iter()
+            .copied()
+            .filter(|&rc| self.at(rc) == Cell::Unknown)
+            .collect()
+    }
+
+    // ship cells a row or column can still take
+    fn room(&self, horizontal: bool, i: usize) -> usize {
+        let target = if horizontal {
+            self.rows[i]
+        } else {
+            self.cols[i]
+        } as usize;
+        target.saturating_sub(
+            line(horizontal, i)
+                .into_iter()
+                .filter(|&rc| self.is_ship(rc))
+                .count(),
+        )
+    }
+
+    pub fn apply(&mut self, step: &Step) {
+        for c in step.ship.iter() {
+            if !self.is_ship((c.row, c.col)) {
+                self.grid[c.row][c.col] = Cell::Ship(Segment::Any);
+            }
+        }
+        for c in step.water.iter() {
+            self.grid[c.row][c.col] = Cell::Water;
+        }
+        self.refine();
+    }
+
+    fn refine(&mut self) {
+        for r in MIN_INDEX..=MAX_INDEX {
+            for c in MIN_INDEX..=MAX_INDEX {
+                if self.is_ship((r, c)) {
+                    self.grid[r][c] = Cell::Ship(self.segment_at(r as i32, c as i32));
+                }
+            }
+        }
+    }
+
+    fn segment_at(&self, r: i32, c: i32) -> Segment {
+        let ship = |r: i32, c: i32| {
+            (0..10).contains(&r) && (0..10).contains(&c) && self.is_ship((r as usize, c as usize))
+        };
+        let (up, down, left, right) = (
+            ship(r - 1, c),
+            ship(r + 1, c),
+            ship(r, c - 1),
+            ship(r, c + 1),
+        );
+        let closed = |dr: i32, dc: i32| self.is_closed(r + dr, c + dc);
+        if (up && down) || (left && right) {
+            Segment::Middle
+        } else if down && closed(-1, 0) {
+            Segment::Top
+        } else if up && closed(1, 0) {
+            Segment::Bottom
+        } else if right && closed(0, -1) {
+            Segment::Left
+        } else if left && closed(0, 1) {
+            Segment::Right
+        } else if closed(-1, 0) && closed(1, 0) && closed(0, -1) && closed(0, 1) {
+            Segment::Single
+        } else {
+            Segment::Any
+        }
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.grid.iter().flatten().all(|c| *c != Cell::Unknown)
+    }
+
+    pub fn render(&self) -> String {
+        let mut result = " 0123456789".to_string();
+        for (i, row) in self.grid.iter().enumerate() {
+            result.push('\n');
+            result.push_str(&i.to_string());
+            for cell in row {
+                result.push(match cell {
+                    Cell::Unknown => '.',
+                    Cell::Water => '~',
+                    Cell::Ship(Segment::Any) => '#',
+                    Cell::Ship(Segment::Single) => 'o',
+                    Cell::Ship(Segment::Top) => '^',
+                    Cell::Ship(Segment::Bottom) => 'v',
+                    Cell::Ship(Segment::Left) => '<',
+                    Cell::Ship(Segment::Right) => '>',
+                    Cell::Ship(Segment::Middle) => '=',
+                });
+            }
+        }
+        result
+    }
+
+    fn saturation(&self) -> Option<Step> {
+        for horizontal in [true, false] {
+            let name = if horizontal { "Row" } else { "Column" };
+            for i in MIN_INDEX..=MAX_INDEX {
+                let cells = line(horizontal, i);
+                let open = self.unknown(&cells);
+                let room = self.room(horizontal, i);
+                if open.is_empty() {
+                    continue;
+                }
+                if room == 0 {
+                    return Some(Step {
+                        technique: Technique::Saturation,
+                        reason: format!("{} {} has all its ship cells, the rest is water", name, i),
+                        ship: vec![],
+                        water: coords(&open),
+                    });
+                }
+                if room == open.len() {
+                    return Some(Step {
+                        technique: Technique::Saturation,
+                        reason: format!("{} {} needs every open cell for its ships", name, i),
+                        ship: coords(&open),
+                        water: vec![],
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn diagonals(&self) -> Option<Step> {
+        for r in MIN_INDEX..=MAX_INDEX {
+            for c in MIN_INDEX..=MAX_INDEX {
+                if !self.is_ship((r, c)) {
+                    continue;
+                }
+                let open = self.unknown(&neighbours(r, c, true));
+                if !open.is_empty() {
+                    return Some(Step {
+                        technique: Technique::DiagonalWater,
+                        reason: format!(
+                            "Ships never touch, cells diagonal to ({}, {}) are water",
+                            r, c
+                        ),
+                        ship: vec![],
+                        water: coords(&open),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    // settled: ship cells in a straight line with water or the edge all around
+    fn is_complete(&self, cells: &[(usize, usize)]) -> bool {
+        cells.iter().all(|&rc| self.is_ship(rc))
+            && cells.iter().all(|&(r, c)| {
+                neighbours(r, c, false)
+                    .iter()
+                    .all(|n| cells.contains(n) || self.at(*n) == Cell::Water)
+            })
+    }
+
+    // Every place a ship of the size could still go. A ship that is already
+    // complete is not a place left for another one.
+    fn candidates(&self, size: usize) -> Vec<Vec<(usize, usize)>> {
+        let mut result = vec![];
+        let orientations: &[bool] = if size == 1 { &[true] } else { &[true, false] };
+        for r in MIN_INDEX..=MAX_INDEX {
+            for c in MIN_INDEX..=MAX_INDEX {
+                for &horizontal in orientations {
+                    if let Some(cells) = segment(r, c, size, horizontal) {
+                        if self.fits(&cells, horizontal) && !self.is_complete(&cells) {
+                            result.push(cells);
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn fits(&self, cells: &[(usize, usize)], horizontal: bool) -> bool {
+        if cells.iter().any(|&rc| self.at(rc) == Cell::Water) {
+            return false;
+        }
+        // the cells around the ship can not hold another ship
+        let touches = cells.iter().any(|&(r, c)| {
+            neighbours(r, c, false)
+                .iter()
+                .any(|n| !cells.contains(n) && self.is_ship(*n))
+        });
+        if touches {
+            return false;
+        }
+        let open = self.unknown(cells);
+        if horizontal {
+            let row = cells[0].0;
+            open.len() <= self.room(true, row) && open.iter().all(|&(_, c)| self.room(false, c) > 0)
+        } else {
+            let col = cells[0].1;
+            open.len() <= self.room(false, col) && open.iter().all(|&(r, _)| self.room(true, r) > 0)
+        }
+    }
+
+    fn ships_left(&self, size: usize) -> usize {
+        let ships = FLEET.iter().filter(|s| **s == size).count();
+        ships.saturating_sub(self.complete_ships(size))
+    }
+
+    // The ship cells connected to the given one.
+    fn part_of_ship(&self, (r, c): (usize, usize)) -> Vec<(usize, usize)> {
+        let mut result = vec![(r, c)];
+        let mut k = 0;
+        while k < result.len() {
+            let (r, c) = result[k];
+            for n in neighbours(r, c, false) {
+                if self.is_ship(n) && !result.contains(&n) {
+                    result.push(n);
+                }
+            }
+            k += 1;
+        }
+        result.sort();
+        result
+    }
+
+    // Once no longer ship is left, a part of a ship is the whole of it.
+    fn exhaustion(&self) -> Option<Step> {
+        let longest_left = FLEET
+            .iter()
+            .copied()
+            .filter(|s| self.ships_left(*s) > 0)
+            .max()
+            .unwrap_or(0);
+        for r in MIN_INDEX..=MAX_INDEX {
+            for c in MIN_INDEX..=MAX_INDEX {
+                if !self.is_ship((r, c)) {
+                    continue;
+                }
+                let cells = self.part_of_ship((r, c));
+                if cells[0] != (r, c) || cells.len() < longest_left {
+                    continue;
+                }
+                let mut water = vec![];
+                for &(r, c) in cells.iter() {
+                    for n in self.unknown(&neighbours(r, c, false)) {
+                        if !water.contains(&n) {
+                            water.push(n);
+                        }
+                    }
+                }
+                if !water.is_empty() {
+                    return Some(Step {
+                        technique: Technique::FleetExhaustion,
+                        reason: format!(
+                            "No ship longer than {} is left, the ship at ({}, {}) ends here",
+                            cells.len(),
+                            r,
+                            c
+                        ),
+                        ship: vec![],
+                        water: coords(&water),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn complete_ships(&self, size: usize) -> usize {
+        let orientations: &[bool] = if size == 1 { &[true] } else { &[true, false] };
+        let mut result = 0;
+        for r in MIN_INDEX..=MAX_INDEX {
+            for c in MIN_INDEX..=MAX_INDEX {
+                for &horizontal in orientations {
+                    if let Some(cells) = segment(r, c, size, horizontal) {
+                        if self.is_complete(&cells) {
+                            result += 1;
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    // Largest ships first: when the places left are exactly as many as the
+    // ships, each of them holds one; a single ship covers what all its places
+    // have in common.
+    fn placement(&self) -> Option<Step> {
+        let mut sizes: Vec<usize> = FLEET.to_vec();
+        sizes.dedup();
+        for size in sizes {
+            let left = self.ships_left(size);
+            if left == 0 {
+                continue;
+            }
+            let candidates = self.candidates(size);
+            if candidates.len() == left {
+                let mut ship = vec![];
+                let mut water = vec![];
+                for cells in candidates.iter() {
+                    ship.extend(self.unknown(cells));
+                    for &(r, c) in cells {
+                        for n in neighbours(r, c, false)
+                            .into_iter()
+                            .chain(neighbours(r, c, true))
+                        {
+                            if !cells.contains(&n)
+                                && self.at(n) == Cell::Unknown
+                                && !water.contains(&n)
+                            {
+                                water.push(n);
+                            }
+                        }
+                    }
+                }
+                if !ship.is_empty() || !water.is_empty() {
+                    let reason = if left == 1 {
+                        format!("The ship of size {} only fits here", size)
+                    } else {
+                        format!("The {} ships of size {} only fit here", left, size)
+                    };
+                    return Some(Step {
+                        technique: Technique::LargestShip,
+                        reason,
+                        ship: coords(&ship),
+                        water: coords(&water),
+                    });
+                }
+            } else if left == 1 && !candidates.is_empty() {
+                let common: Vec<(usize, usize)> = self
+                    .unknown(&candidates[0])
+                    .into_iter()
+                    .filter(|rc| candidates.iter().all(|cells| cells.contains(rc)))
+                    .collect();
+                if !common.is_empty() {
+                    return Some(Step {
+                        technique: Technique::LargestShip,
+                        reason: format!(
+                            "Every place left for the ship of size {} covers these cells",
+                            size
+                        ),
+                        ship: coords(&common),
+                        water: vec![],
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    pub fn next_step(&self) -> Option<Step> {
+        self.saturation()
+            .or_else(|| self.diagonals())
+            .or_else(|| self.exhaustion())
+            .or_else(|| self.placement())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Explanation {
+    pub steps: Vec<Step>,
+    // true when the steps alone solve the puzzle
+    pub solved: bool,
+    pub difficulty: Difficulty,
+}
+
+// Rated by the hardest technique the explanation needs.
+pub fn difficulty(steps: &[Step]) -> Difficulty {
+    match steps.iter().map(|s| s.technique).max() {
+        Some(Technique::Solution) => Difficulty::Hard,
+        Some(Technique::FleetExhaustion) | Some(Technique::LargestShip) => Difficulty::Medium,
+        _ => Difficulty::Easy,
+    }
+}
+
+// The difficulty of the puzzle with the hints v2 serves for number_of_hints.
+pub fn rate(sf: &SetupFormat) -> Difficulty {
+    let (explanation, _) = explain(&sf.setup, &hints_for(sf, number_of_hints(sf)));
+    explanation.difficulty
+}
+
+// Deduces the solution from the counts and the revealed hints. When no rule
+// applies any more the remaining cells are taken from the solution.
+pub fn explain(setup: &Setup, hints: &[Coord]) -> (Explanation, Vec<Board>) {
+    let mut board = Board::from_puzzle(&Puzzle::from_setup(setup));
+    let mut steps = vec![];
+    let mut boards = vec![board];
+    if !hints.is_empty() {
+        steps.push(Step {
+            technique: Technique::Hints,
+            reason: "The hints reveal ship cells".to_string(),
+            ship: hints.to_vec(),
+            water: vec![],
+        });
+    }
+    if let Some(step) = steps.first() {
+        board.apply(step);
+        boards.push(board);
+    }
+    while let Some(step) = board.next_step() {
+        board.apply(&step);
+        boards.push(board);
+        steps.push(step);
+    }
+    let solved = board.is_solved();
+    if !solved {
+        let step = from_solution(setup, &board, usize::MAX);
+        board.apply(&step);
+        boards.push(board);
+        steps.push(step);
+    }
+    let difficulty = difficulty(&steps);
+    (
+        Explanation {
+            steps,
+            solved,
+            difficulty,
+        },
+        boards,
+    )
+}
+
+// Reveals up to `limit` open cells when no rule applies.
+fn from_solution(setup: &Setup, board: &Board, limit: usize) -> Step {
+    let mut ship = vec![];
+    let mut water = vec![];
+    let open: Vec<(usize, usize)> = (MIN_INDEX..=MAX_INDEX)
+        .flat_map(|r| (MIN_INDEX..=MAX_INDEX).map(move |c| (r, c)))
+        .filter(|&rc| board.at(rc) == Cell::Unknown)
+        .take(limit)
+        .collect();
+    for (r, c) in open {
+        if setup[r][c] > 0 {
+            ship.push((r, c));
+        } else {
+            water.push((r, c));
+        }
+    }
+    Step {
+        technique: Technique::Solution,
+        reason: "No rule applies any more, the rest is taken from the solution".to_string(),
+        ship: coords(&ship),
+        water: coords(&water),
+    }
+}
+
+// The deduction that follows from the marks made so far, which have to agree
+// with the solution.
+pub fn next_step(setup: &Setup, hints: &[Coord], partial: &PartialSolution) -> Option<Step> {
+    let wrong = partial.ships.iter().any(|c| setup[c.row][c.col] == 0)
+        || partial.water.iter().any(|c| setup[c.row][c.col] > 0);
+    if wrong {
+        return None;
+    }
+    let mut board = Board::from_puzzle(&Puzzle::from_setup(setup));
+    board.apply(&Step {
+        technique: Technique::Hints,
+        reason: String::new(),
+        ship: hints.iter().chain(partial.ships.iter()).copied().collect(),
+        water: partial.water.clone(),
+    });
+    if board.is_solved() {
+        return Some(Step {
+            technique: Technique::Solution,
+            reason: "The board is solved".to_string(),
+            ship: vec![],
+            water: vec![],
+        });
+    }
+    Some(
+        board
+            .next_step()
+            .unwrap_or_else(|| from_solution(setup, &board, 1)),
+    )
+}
+
+fn explanation_of(req: &Request<State>) -> Option<(Explanation, Vec<Board>)> {
+    let game_setup_id = Uuid::from_str(req.param("setup_id").ok()?).ok()?;
+    let setup = req.state().setup(&game_setup_id)?.setup;
+    Some(explain(&setup, &find_hints(&setup, hint_limit(req))))
+}
+
+pub async fn steps(req: Request<State>) -> tide::Result {
+    match explanation_of(&req) {
+        Some((explanation, _)) => yaml_response(200, CT_YAML, &explanation),
+        None => not_found("Unknown game setup"),
+    }
+}
+
+pub async fn next(mut req: Request<State>) -> tide::Result {
+    let game_setup_str: &str = req.param("setup_id")?;
+    if let Ok(game_setup_id) = Uuid::from_str(game_setup_str) {
+        if let Some(setup) = req.state().setup(&game_setup_id) {
+            if let Ok(body_str) = req.body_string().await {
+                if let Ok(entity) = serde_yaml::from_str::<PartialSolution>(&body_str) {
+                    if entity
+                        .ships
+                        .iter()
+                        .chain(entity.water.iter())
+                        .any(|c| c.row > 9 || c.col > 9)
+                    {
+                        return unprocessable("Coordinate is off the board");
+                    }
+                    let hints_used = match session_of(&req, &game_setup_id) {
+                        Some(session) => session.hints_used,
+                        None => 0,
+                    };
+                    let hints = find_hints(&setup.setup, hints_used);
+                    match next_step(&setup.setup, &hints, &entity) {
+                        Some(step) => yaml_response(200, CT_YAML, &step),
+                        None => unprocessable("Some of the marks are wrong"),
+                    }
+                } else {
+                    illegal_request("Could not parse entity")
+                }
+            } else {
+                illegal_request("Could not read the request")
+            }
+        } else {
+            not_found("Unknown game setup")
+        }
+    } else {
+        not_found("Game setup id not found")
+    }
+}
+
+pub async fn boards(req: Request<State>) -> tide::Result {
+    match explanation_of(&req) {
+        Some((explanation, boards)) => {
+            let mut text = format!("Start\n{}\n", boards[0].render());
+            for (i, (step, board)) in explanation.steps.iter().zip(&boards[1..]).enumerate() {
+                text.push_str(&format!(
+                    "\nStep {}: {}\n{}\n",
+                    i + 1,
+                    step.reason,
+                    board.render()
+                ));
+            }
+            text_response(&text)
+        }
+        None => not_found("Unknown game setup"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_app;
+    use crate::setups::{build_all, GAME_2};
+    use tide_testing::TideTestingExt;
+
+    #[test]
+    fn test_steps_agree_with_setup() {
+        for sf in build_all().values() {
+            let setup = sf.setup;
+            for limit in [0, 10] {
+                let (explanation, boards) = explain(&setup, &find_hints(&setup, limit));
+                assert_eq!(boards.len(), explanation.steps.len() + 1);
+                for step in explanation.steps.iter() {
+                    assert!(step.ship.iter().all(|c| setup[c.row][c.col] > 0));
+                    assert!(step.water.iter().all(|c| setup[c.row][c.col] == 0));
+                }
+                let last = boards.last().unwrap();
+                assert!(last.is_solved());
+                let ships: Vec<(usize, usize)> = (MIN_INDEX..=MAX_INDEX)
+                    .flat_map(|r| (MIN_INDEX..=MAX_INDEX).map(move |c| (r, c)))
+                    .filter(|&rc| last.is_ship(rc))
+                    .collect();
+                assert_eq!(ships, crate::setups::cells(&setup));
+            }
+        }
+    }
+
+    #[test]
+    fn test_saturation() {
+        let mut rows = [0; 10];
+        rows[3] = 4;
+        let board = Board::new(rows, [0; 10]);
+        let step = board.next_step().unwrap();
+        assert_eq!(
+            step.reason,
+            "Row 0 has all its ship cells, the rest is water"
+        );
+        assert_eq!(step.water.len(), 10);
+        assert!(step.ship.is_empty());
+    }
+
+    fn ships(board: &mut Board, cells: &[(usize, usize)]) {
+        board.apply(&Step {
+            technique: Technique::Hints,
+            reason: String::new(),
+            ship: coords(cells),
+            water: vec![],
+        });
+    }
+
+    #[test]
+    fn test_segments() {
+        let mut board = Board::new([1; 10], [1; 10]);
+        ships(&mut board, &[(0, 0), (0, 1), (0, 2), (5, 5)]);
+        assert_eq!(board.at((0, 0)), Cell::Ship(Segment::Left));
+        assert_eq!(board.at((0, 1)), Cell::Ship(Segment::Middle));
+        assert_eq!(board.at((0, 2)), Cell::Ship(Segment::Any));
+        assert_eq!(board.at((5, 5)), Cell::Ship(Segment::Any));
+        let water = neighbours(5, 5, false)
+            .iter()
+            .map(|&(row, col)| Coord { row, col })
+            .collect::<Vec<Coord>>();
+        board.apply(&Step {
+            technique: Technique::Hints,
+            reason: String::new(),
+            ship: vec![],
+            water,
+        });
+        assert_eq!(board.at((5, 5)), Cell::Ship(Segment::Single));
+        assert_eq!(board.render().lines().nth(6), Some("5....~o~..."));
+    }
+
+    #[test]
+    fn test_fleet_exhaustion() {
+        let mut board = Board::new([4, 0, 1, 1, 1, 0, 0, 0, 0, 0], [1; 10]);
+        ships(
+            &mut board,
+            &[(0, 0), (0, 1), (0, 2), (0, 3), (2, 5), (3, 5), (4, 5)],
+        );
+        board.apply(&Step {
+            technique: Technique::Hints,
+            reason: String::new(),
+            ship: vec![],
+            water: coords(&[(0, 4), (1, 0), (1, 1), (1, 2), (1, 3), (1, 5)]),
+        });
+        // with the ship of size 4 complete, the run of three is a whole ship
+        let step = board.exhaustion().unwrap();
+        assert_eq!(step.technique, Technique::FleetExhaustion);
+        assert_eq!(step.water.len(), 7);
+        assert!(step.water.contains(&Coord { row: 5, col: 5 }));
+    }
+
+    #[test]
+    fn test_largest_ship() {
+        let mut rows = [0; 10];
+        rows[9] = 4;
+        let mut cols = [0; 10];
+        cols[6..10].copy_from_slice(&[1, 1, 1, 1]);
+        let board = Board::new(rows, cols);
+        let step = board.placement().unwrap();
+        assert_eq!(step.technique, Technique::LargestShip);
+        assert_eq!(step.ship, coords(&[(9, 6), (9, 7), (9, 8), (9, 9)]));
+        assert_eq!(step.reason, "The ship of size 4 only fits here");
+    }
+
+    #[test]
+    fn test_rate() {
+        let sf = build_all()
+            .values()
+            .copied()
+            .find(|sf| sf.setup == GAME_2)
+            .unwrap();
+        let hints = hints_for(&sf, number_of_hints(&sf));
+        assert_eq!(rate(&sf), explain(&GAME_2, &hints).0.difficulty);
+        // with every ship cell revealed the counts settle the rest
+        let all = coords(&crate::setups::cells(&GAME_2));
+        assert_eq!(explain(&GAME_2, &all).0.difficulty, Difficulty::Easy);
+    }
+
+    #[async_std::test]
+    async fn test_explanation() {
+        let app = build_app();
+        let id = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+        let explanation: Explanation = serde_yaml::from_str(
+            &app.get(format!("/game/{}/explanation?limit=3", id))
+                .recv_string()
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(explanation.steps[0].ship.len(), 3);
+        assert_eq!(explanation.steps[0].technique, Technique::Hints);
+        assert_eq!(difficulty(&explanation.steps), explanation.difficulty);
+
+        let boards = app
+            .get(format!("/game/{}/explanation/boards", id))
+            .recv_string()
+            .await
+            .unwrap();
+        assert!(boards.starts_with("Start\n 0123456789"));
+        assert!(boards.contains("Step 1: "));
+
+        let step: Step = serde_yaml::from_str(
+            &app.post(format!("/game/{}/explanation/next", id))
+                .body_string("ships: []".to_string())
+                .recv_string()
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(step.technique, Technique::Saturation);
+        let resp = app
+            .post(format!("/game/{}/explanation/next", id))
+            .body_string("ships: [{row: 0, col: 0}]".to_string())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 422);
+        let resp = app
+            .post(format!("/game/{}/explanation/next", id))
+            .body_string("water: [{row: 10, col: 0}]".to_string())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 422);
+
+        let resp = app
+            .get(format!("/game/{}/explanation", Uuid::new_v4()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::admin::{forbidden, is_admin};
+use crate::{shutdown, State};
+use async_std::channel::{bounded, Receiver, RecvError, Sender};
+use futures_lite::FutureExt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use tide::sse::Sender as SseSender;
+use tide::{Endpoint, Request};
+use uuid::Uuid;
+
+const SUBSCRIBER_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum GameEvent {
+    GameCreated {
+        #[serde(with = "crate::uuid_as_string")]
+        #[schemars(with = "Uuid")]
+        setup_id: Uuid,
+        player_id: String,
+    },
+    HintServed {
+        #[serde(with = "crate::uuid_as_string")]
+        #[schemars(with = "Uuid")]
+        setup_id: Uuid,
+        player_id: String,
+        hints: u8,
+    },
+    CheckFailed {
+        #[serde(with = "crate::uuid_as_string")]
+        #[schemars(with = "Uuid")]
+        setup_id: Uuid,
+        player_id: String,
+    },
+    CheckSolved {
+        #[serde(with = "crate::uuid_as_string")]
+        #[schemars(with = "Uuid")]
+        setup_id: Uuid,
+        player_id: String,
+    },
+}
+
+impl GameEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameEvent::GameCreated { .. } => "game-created",
+            GameEvent::HintServed { .. } => "hint-served",
+            GameEvent::CheckFailed { .. } => "check-failed",
+            GameEvent::CheckSolved { .. } => "check-solved",
+        }
+    }
+}
+
+pub struct Bus {
+    salt: Uuid,
+    subscribers: Mutex<Vec<Sender<GameEvent>>>,
+}
+
+impl Default for Bus {
+    fn default() -> Bus {
+        Bus {
+            salt: Uuid::new_v4(),
+            subscribers: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl Bus {
+    pub fn subscribe(&self) -> Receiver<GameEvent> {
+        let (sender, receiver) = bounded(SUBSCRIBER_CAPACITY);
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    // slow subscribers miss events instead of blocking the handlers
+    pub fn publish(&self, event: GameEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|s| match s.try_send(event.clone()) {
+                Ok(()) => true,
+                Err(e) => !e.is_closed(),
+            });
+    }
+
+    pub fn anonymize(&self, player: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.salt.hash(&mut hasher);
+        player.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+pub async fn events(req: Request<State>) -> tide::Result {
+    if is_admin(&req) {
+        tide::sse::endpoint(stream).call(req).await
+    } else {
+        forbidden()
+    }
+}
+
+async fn stream(req: Request<State>, sender: SseSender) -> tide::Result<()> {
+    let receiver = req.state().bus.subscribe();
+    let stopped = || async {
+        shutdown::stopped(req.state()).await;
+        Err(RecvError)
+    };
+    while let Ok(event) = receiver.recv().or(stopped()).await {
+        sender
+            .send(event.name(), &serde_json::to_string(&event)?, None)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_app_from;
+    use crate::setups::build_all;
+    use crate::storage::MemoryStorage;
+    use futures_lite::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tide_testing::TideTestingExt;
+
+    #[test]
+    fn test_bus() {
+        let bus = Bus::default();
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+        drop(second);
+        let event = GameEvent::GameCreated {
+            setup_id: Uuid::new_v4(),
+            player_id: bus.anonymize("alice"),
+        };
+        bus.publish(event.clone());
+        assert_eq!(first.try_recv().unwrap(), event);
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+        assert_eq!(bus.anonymize("alice"), bus.anonymize("alice"));
+        assert_ne!(bus.anonymize("alice"), bus.anonymize("bob"));
+        assert!(!bus.anonymize("alice").contains("alice"));
+    }
+
+    #[async_std::test]
+    async fn test_event_stream() {
+        let token = Some("secret".to_string());
+        let app = build_app_from(build_all(), Arc::new(MemoryStorage::new()), token);
+        let resp = app.get("/events").await.unwrap();
+        assert_eq!(resp.status(), 403);
+        let mut resp = app
+            .get("/events")
+            .header("Authorization", "Bearer secret")
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        // the stream subscribes once the response is under way
+        while app.state().bus.subscribers.lock().unwrap().is_empty() {
+            async_std::task::yield_now().await;
+        }
+
+        let setup_id = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+        app.post(format!("/game/{}?player=alice", setup_id))
+            .await
+            .unwrap();
+        let mut body = BufReader::new(resp.take_body());
+        let mut lines = vec![];
+        for _ in 0..2 {
+            let mut line = String::new();
+            body.read_line(&mut line).await.unwrap();
+            lines.push(line);
+        }
+        assert_eq!(lines[0], "event:game-created\n");
+        let data = lines[1].strip_prefix("data:").unwrap();
+        assert_eq!(
+            serde_json::from_str::<GameEvent>(data).unwrap(),
+            GameEvent::GameCreated {
+                setup_id,
+                player_id: app.state().bus.anonymize("alice"),
+            }
+        );
+
+        // an open stream ends once the server is stopping
+        app.state().stopping.store(true, Ordering::SeqCst);
+        let mut rest = String::new();
+        async_std::future::timeout(Duration::from_secs(5), body.read_to_string(&mut rest))
+            .await
+            .expect("the stream did not end")
+            .unwrap();
+    }
+}
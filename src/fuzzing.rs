@@ -0,0 +1,245 @@
+use crate::live::{parse_command, LiveBoard};
+use crate::setups::{build_all, SetupFormat, Setups, GAME_2};
+use crate::storage::MemoryStorage;
+use crate::{build_app_from, State};
+use bimaru_protocol::{
+    v2, Check, EncodingStyle, Hints, NestedHints, NestedNewGame, NewGame, WireFormat,
+};
+use std::sync::Arc;
+use tide::http::{Method, Request, Response, Url};
+use tide::Server;
+use uuid::Uuid;
+
+// Entry points of the fuzz targets in fuzz/. Each input runs against a fresh
+// app so nothing piles up between inputs, and any answer of 500 or above
+// counts as a failure just like a panic.
+
+// (method, path), {setup}, {battle} and {tournament} are filled in
+const BODY_ROUTES: [(Method, &str); 12] = [
+    (Method::Post, "/game/{setup}"),
+    (Method::Post, "/game/{setup}/check"),
+    (Method::Post, "/v1/game/{setup}/check"),
+    (Method::Post, "/v2/game/{setup}/check"),
+    (Method::Post, "/game/{setup}/validate"),
+    (Method::Post, "/v2/game/{setup}/validate"),
+    (Method::Post, "/game/{setup}/explanation/next"),
+    (Method::Post, "/tournaments"),
+    (Method::Post, "/tournaments/{tournament}/teams"),
+    (Method::Put, "/roster"),
+    (Method::Post, "/battles/{battle}/players"),
+    (Method::Post, "/battles/{battle}/shots"),
+];
+
+const QUERY_ROUTES: [(Method, &str); 7] = [
+    (Method::Get, "/game/{setup}/hint"),
+    (Method::Get, "/v2/game/{setup}/hint"),
+    (Method::Get, "/game/{setup}/explanation"),
+    (Method::Get, "/game/{setup}/explanation/boards"),
+    (Method::Post, "/game/{setup}"),
+    (Method::Post, "/v2/game/{setup}"),
+    (Method::Get, "/daily/history"),
+];
+
+// Every request carries the token, so the admin routes are reached as well.
+const ADMIN_TOKEN: &str = "fuzz";
+
+struct Target {
+    app: Server<State>,
+    setups: Vec<Uuid>,
+    battle: String,
+    tournament: String,
+}
+
+// The catalog with every wire format and encoding style spread over its
+// setups, so the binary formats are reached as well.
+fn fuzz_setups() -> Setups {
+    let all = build_all();
+    let mut ids: Vec<Uuid> = all.keys().copied().collect();
+    ids.sort();
+    let formats = WireFormat::ALL.len();
+    ids.iter()
+        .enumerate()
+        .map(|(i, id)| {
+            let sf = SetupFormat {
+                wire_format: WireFormat::ALL[i % formats],
+                encoding: EncodingStyle::ALL[i / formats % EncodingStyle::ALL.len()],
+                ..all[id]
+            };
+            (*id, sf)
+        })
+        .collect()
+}
+
+fn field(resp: &mut Response, name: &str) -> String {
+    let body = async_std::task::block_on(resp.body_string()).unwrap();
+    let value: serde_yaml::Value = serde_yaml::from_str(&body).unwrap();
+    value[name].as_str().unwrap().to_string()
+}
+
+impl Target {
+    fn new() -> Target {
+        let setups = fuzz_setups();
+        let mut ids: Vec<Uuid> = setups.keys().copied().collect();
+        ids.sort();
+        let mut target = Target {
+            app: build_app_from(
+                setups,
+                Arc::new(MemoryStorage::new()),
+                Some(ADMIN_TOKEN.to_string()),
+            ),
+            setups: ids,
+            battle: String::new(),
+            tournament: String::new(),
+        };
+        let mut resp = target.send(Method::Post, "/battles", None, None, vec![]);
+        target.battle = field(&mut resp, "battle_id");
+        let spec = format!(
+            "name: fuzz\nsetups: [{}]\nstarts_at: 0\nround_millis: 60000\n",
+            target.setups[0]
+        );
+        let mut resp = target.send(Method::Post, "/tournaments", None, None, spec.into_bytes());
+        target.tournament = field(&mut resp, "tournament_id");
+        target
+    }
+
+    fn path(&self, template: &str, pick: u8) -> String {
+        let setup = self.setups[pick as usize % self.setups.len()];
+        template
+            .replace("{setup}", &setup.to_string())
+            .replace("{battle}", &self.battle)
+            .replace("{tournament}", &self.tournament)
+    }
+
+    fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: Option<&str>,
+        content_type: Option<&str>,
+        body: Vec<u8>,
+    ) -> Response {
+        let mut url = Url::parse("http://localhost").unwrap().join(path).unwrap();
+        url.set_query(query);
+        let mut req = Request::new(method, url);
+        req.insert_header("Authorization", format!("Bearer {}", ADMIN_TOKEN));
+        req.set_body(body);
+        if let Some(content_type) = content_type {
+            req.insert_header("Content-Type", content_type);
+        }
+        let resp: Response = async_std::task::block_on(self.app.respond(req)).unwrap();
+        assert!(
+            !resp.status().is_server_error(),
+            "{} {} answered {}",
+            method,
+            path,
+            resp.status()
+        );
+        resp
+    }
+}
+
+// Every content type the server tells apart, none at all first.
+fn content_types() -> Vec<Option<String>> {
+    let mut result = vec![None];
+    for format in WireFormat::ALL {
+        for style in EncodingStyle::ALL {
+            result.push(Some(style.content_type(format)));
+        }
+    }
+    result
+}
+
+// The first byte picks a route taking a body, the second the setup, the third
+// the content type and the rest is the body.
+pub fn body(data: &[u8]) {
+    if data.len() < 3 {
+        return;
+    }
+    let target = Target::new();
+    let (method, template) = BODY_ROUTES[data[0] as usize % BODY_ROUTES.len()];
+    let types = content_types();
+    let content_type = &types[data[2] as usize % types.len()];
+    target.send(
+        method,
+        &target.path(template, data[1]),
+        None,
+        content_type.as_deref(),
+        data[3..].to_vec(),
+    );
+}
+
+// The first byte picks a route reading a query, the second the setup and the
+// rest is the query string.
+pub fn query(data: &[u8]) {
+    if data.len() < 2 {
+        return;
+    }
+    let target = Target::new();
+    let (method, template) = QUERY_ROUTES[data[0] as usize % QUERY_ROUTES.len()];
+    let query = String::from_utf8_lossy(&data[2..]);
+    target.send(
+        method,
+        &target.path(template, data[1]),
+        Some(&query),
+        None,
+        vec![],
+    );
+}
+
+// The bytes as every entity in every wire format and encoding style, and as
+// a command of a live game.
+pub fn wire(data: &[u8]) {
+    for format in WireFormat::ALL {
+        let _ = format.decode::<NewGame>(data);
+        let _ = format.decode::<NestedNewGame>(data);
+        let _ = format.decode::<Check>(data);
+        let _ = format.decode::<Hints>(data);
+        let _ = format.decode::<NestedHints>(data);
+        let _ = format.decode::<v2::NewGame>(data);
+        let _ = format.decode::<v2::Check>(data);
+        for style in EncodingStyle::ALL {
+            let _ = style.decode_coords(format, data);
+        }
+    }
+    if let Ok(input) = std::str::from_utf8(data) {
+        if let Ok(command) = parse_command(input) {
+            LiveBoard::new(GAME_2).handle(command);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    // Replays the checked-in corpus, inputs found by fuzzing stay fixed.
+    fn replay(target: &str, run: fn(&[u8])) {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz/corpus")
+            .join(target);
+        let mut count = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            run(&fs::read(&path).unwrap());
+            count += 1;
+        }
+        assert!(count > 0, "no corpus in {}", dir.display());
+    }
+
+    #[test]
+    fn test_body_corpus() {
+        replay("body", body);
+    }
+
+    #[test]
+    fn test_query_corpus() {
+        replay("query", query);
+    }
+
+    #[test]
+    fn test_wire_corpus() {
+        replay("wire", wire);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::setups::{has_fleet, is_valid};
+use crate::{text_response, yaml_response, State, CT_PLAIN, CT_YAML};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+use tide::{Request, Response};
+
+#[derive(Serialize, JsonSchema)]
+pub struct BuildInfo {
+    name: &'static str,
+    version: &'static str,
+    revision: Option<&'static str>,
+}
+
+const BUILD_INFO: BuildInfo = BuildInfo {
+    name: env!("CARGO_PKG_NAME"),
+    version: env!("CARGO_PKG_VERSION"),
+    revision: option_env!("GIT_REVISION"),
+};
+
+pub async fn healthz(_req: Request<State>) -> tide::Result {
+    text_response("OK")
+}
+
+pub async fn readyz(req: Request<State>) -> tide::Result {
+    let state = req.state();
+    if state.stopping.load(Ordering::SeqCst) {
+        not_ready("Server is shutting down")
+    } else if !state.ready.load(Ordering::SeqCst) {
+        not_ready("Listener is not bound")
+    } else if state.setups.is_empty() {
+        not_ready("Catalog is empty")
+    } else if !state
+        .setups
+        .values()
+        .all(|sf| is_valid(&sf.setup) && has_fleet(&sf.setup))
+    {
+        not_ready("Catalog contains invalid setups")
+    } else {
+        text_response("OK")
+    }
+}
+
+pub async fn version(_req: Request<State>) -> tide::Result {
+    yaml_response(200, CT_YAML, &BUILD_INFO)
+}
+
+fn not_ready(text: &str) -> tide::Result {
+    Ok(Response::builder(503)
+        .body(text)
+        .content_type(CT_PLAIN)
+        .build())
+}
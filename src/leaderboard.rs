@@ -0,0 +1,231 @@
+use crate::storage::Session;
+use crate::{not_found, yaml_response, State, CT_YAML};
+use im::HashMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tide::Request;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct SetupEntry {
+    pub player: String,
+    pub time_to_solve: u64,
+    pub hints_used: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct GlobalEntry {
+    pub player: String,
+    pub setups_solved: usize,
+    pub total_time_to_solve: u64,
+    pub total_hints_used: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct SetupBest {
+    #[serde(with = "crate::uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub game_setup_id: Uuid,
+    pub time_to_solve: u64,
+    pub hints_used: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct PlayerStats {
+    pub player: String,
+    pub games_started: usize,
+    pub games_solved: usize,
+    pub check_attempts: u32,
+    pub hints_used: u32,
+    pub best: Vec<SetupBest>,
+}
+
+fn time_to_solve(s: &Session) -> Option<u64> {
+    s.completed_at.map(|c| c.saturating_sub(s.started_at))
+}
+
+// best solve (fastest, then fewest hints) of each player for each setup
+fn best_solves(sessions: &[Session]) -> HashMap<(String, Uuid), SetupEntry> {
+    let mut result: HashMap<(String, Uuid), SetupEntry> = HashMap::new();
+    for s in sessions {
+        if let (Some(player), Some(time)) = (&s.player, time_to_solve(s)) {
+            let entry = SetupEntry {
+                player: player.clone(),
+                time_to_solve: time,
+                hints_used: s.hints_used,
+            };
+            let key = (player.clone(), s.setup_id);
+            let better = match result.get(&key) {
+                Some(e) => (time, s.hints_used) < (e.time_to_solve, e.hints_used),
+                None => true,
+            };
+            if better {
+                result.insert(key, entry);
+            }
+        }
+    }
+    result
+}
+
+pub fn setup_leaderboard(sessions: &[Session], setup_id: &Uuid) -> Vec<SetupEntry> {
+    let mut result: Vec<SetupEntry> = best_solves(sessions)
+        .into_iter()
+        .filter(|((_, id), _)| id == setup_id)
+        .map(|(_, e)| e)
+        .collect();
+    result.sort_by(|a, b| {
+        (a.time_to_solve, a.hints_used, &a.player).cmp(&(b.time_to_solve, b.hints_used, &b.player))
+    });
+    result
+}
+
+pub fn global_leaderboard(sessions: &[Session]) -> Vec<GlobalEntry> {
+    let mut by_player: HashMap<String, GlobalEntry> = HashMap::new();
+    for ((player, _), e) in best_solves(sessions) {
+        let entry = by_player.entry(player.clone()).or_insert(GlobalEntry {
+            player,
+            setups_solved: 0,
+            total_time_to_solve: 0,
+            total_hints_used: 0,
+        });
+        entry.setups_solved += 1;
+        entry.total_time_to_solve += e.time_to_solve;
+        entry.total_hints_used += e.hints_used as u32;
+    }
+    let mut result: Vec<GlobalEntry> = by_player.into_iter().map(|(_, e)| e).collect();
+    result.sort_by(|a, b| {
+        (
+            b.setups_solved,
+            a.total_time_to_solve,
+            a.total_hints_used,
+            &a.player,
+        )
+            .cmp(&(
+                a.setups_solved,
+                b.total_time_to_solve,
+                b.total_hints_used,
+                &b.player,
+            ))
+    });
+    result
+}
+
+pub fn player_stats(sessions: &[Session], player: &str) -> Option<PlayerStats> {
+    let own: Vec<Session> = sessions
+        .iter()
+        .filter(|s| s.player.as_deref() == Some(player))
+        .cloned()
+        .collect();
+    if own.is_empty() {
+        return None;
+    }
+    let mut best: Vec<SetupBest> = best_solves(&own)
+        .into_iter()
+        .map(|((_, game_setup_id), e)| SetupBest {
+            game_setup_id,
+            time_to_solve: e.time_to_solve,
+            hints_used: e.hints_used,
+        })
+        .collect();
+    best.sort_by_key(|b| b.game_setup_id);
+    Some(PlayerStats {
+        player: player.to_string(),
+        games_started: own.len(),
+        games_solved: own.iter().filter(|s| s.completed_at.is_some()).count(),
+        check_attempts: own.iter().map(|s| s.check_attempts).sum(),
+        hints_used: own.iter().map(|s| s.hints_used as u32).sum(),
+        best,
+    })
+}
+
+pub async fn global(req: Request<State>) -> tide::Result {
+    let sessions = req.state().storage.sessions();
+    yaml_response(200, CT_YAML, &global_leaderboard(&sessions))
+}
+
+pub async fn per_setup(req: Request<State>) -> tide::Result {
+    let game_setup_str: &str = req.param("setup_id")?;
+    if let Ok(game_setup_id) = Uuid::from_str(game_setup_str) {
+        if req.state().setup(&game_setup_id).is_some() {
+            let sessions = req.state().storage.sessions();
+            yaml_response(200, CT_YAML, &setup_leaderboard(&sessions, &game_setup_id))
+        } else {
+            not_found("Unknown game setup")
+        }
+    } else {
+        not_found("Game setup id not found")
+    }
+}
+
+pub async fn stats(req: Request<State>) -> tide::Result {
+    let player: &str = req.param("player_id")?;
+    let sessions = req.state().storage.sessions();
+    if let Some(stats) = player_stats(&sessions, player) {
+        yaml_response(200, CT_YAML, &stats)
+    } else {
+        not_found("Unknown player")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(player: &str, setup_id: Uuid, time: Option<u64>, hints_used: u8) -> Session {
+        Session {
+            id: Uuid::new_v4(),
+            setup_id,
+            player: Some(player.to_string()),
+            started_at: 1000,
+            hints_used,
+            check_attempts: 1,
+            completed_at: time.map(|t| 1000 + t),
+            team_token: None,
+        }
+    }
+
+    #[test]
+    fn test_leaderboards() {
+        let a = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+        let b = uuid::uuid!("37073150-f43d-4609-94ec-dcbeffcb472a");
+        let sessions = vec![
+            session("alice", a, Some(50), 2),
+            session("alice", a, Some(40), 5),
+            session("alice", b, None, 10),
+            session("bob", a, Some(30), 0),
+            session("bob", b, Some(90), 1),
+            session("carol", a, None, 3),
+        ];
+        assert_eq!(
+            setup_leaderboard(&sessions, &a),
+            vec![
+                SetupEntry {
+                    player: "bob".to_string(),
+                    time_to_solve: 30,
+                    hints_used: 0
+                },
+                SetupEntry {
+                    player: "alice".to_string(),
+                    time_to_solve: 40,
+                    hints_used: 5
+                },
+            ]
+        );
+        let global: Vec<(String, usize)> = global_leaderboard(&sessions)
+            .into_iter()
+            .map(|e| (e.player, e.setups_solved))
+            .collect();
+        assert_eq!(
+            global,
+            vec![("bob".to_string(), 2), ("alice".to_string(), 1)]
+        );
+
+        let stats = player_stats(&sessions, "alice").unwrap();
+        assert_eq!(stats.games_started, 3);
+        assert_eq!(stats.games_solved, 2);
+        assert_eq!(stats.hints_used, 17);
+        assert_eq!(stats.best.len(), 1);
+        assert_eq!(player_stats(&sessions, "dave"), None);
+    }
+}
@@ -0,0 +1,1403 @@
+mod admin;
+mod battle;
+pub mod bitboard;
+mod daily;
+pub mod deduce;
+mod events;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
+mod health;
+mod leaderboard;
+mod limits;
+mod live;
+mod openapi;
+mod roster;
+pub mod search;
+pub mod setups;
+mod shutdown;
+pub mod storage;
+mod tournament;
+mod v2;
+mod validate;
+
+pub use bimaru_protocol::{uuid_as_string, Check, Coord, NonEmptyList};
+
+use bimaru_protocol::{
+    to_non_empty_list, Bitmap, ConsList, EncodingStyle, Hints, NestedNewGame, NewGame, RunLength,
+    A1, CT_PLAIN, CT_YAML, SESSION_HEADER,
+};
+use futures_lite::FutureExt;
+use openapi::{Doc, Routes};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use setups::{
+    build_all, CreateFormat, OccupiedCells, Searchable, Setup, SetupFormat, Setups, WireFormat,
+};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use events::{Bus, GameEvent};
+use storage::{now_millis, Event, MemoryStorage, Session, Storage};
+use tide::listener::Listener;
+use tide::{Request, Response, Server};
+use uuid::Uuid;
+use string_builder::Builder;
+
+pub const MAX_HINTS: u8 = 10;
+const CT_EVENT_STREAM: &str = "text/event-stream";
+const DEPRECATION_HEADER: &str = "Deprecation";
+
+pub trait IsSolved {
+    fn solves(&self, setup: Setup) -> bool;
+}
+
+impl IsSolved for Check {
+    fn solves(&self, setup: Setup) -> bool {
+        if self.coords.len() == 20 {
+            for i in setups::MIN_INDEX..=setups::MAX_INDEX {
+                for j in setups::MIN_INDEX..=setups::MAX_INDEX {
+                    if setup[i][j] > 0 && !self.coords.contains(&Coord { col: j, row: i }) {
+                        return false;
+                    }
+                }
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub fn find_hints(setup: &Setup, limit: u8) -> Vec<Coord> {
+    let mut result = Vec::with_capacity(limit as usize);
+    for n in 1..=limit {
+        if let Some(t) = setup.find_position(n) {
+            result.push(Coord { row: t.0, col: t.1 });
+        }
+    }
+    result
+}
+
+// The irreducible set first, so number_of_hints hints leave one solution, then the
+// other ship cells in their 1..n numbering.
+pub fn hints_for(sf: &SetupFormat, limit: u8) -> Vec<Coord> {
+    let mut result: Vec<Coord> = sf.irreducible_hints.map(Vec::from).unwrap_or_default();
+    for c in find_hints(&sf.setup, 20) {
+        if !result.contains(&c) {
+            result.push(c);
+        }
+    }
+    result.truncate(limit as usize);
+    result
+}
+
+// Only /v2 serves the irreducible set; the unprefixed and /v1 routes keep find_hints.
+pub(crate) fn served_hints(req: &Request<State>, sf: &SetupFormat, limit: u8) -> Vec<Coord> {
+    if req.url().path().starts_with("/v2/") {
+        hints_for(sf, limit)
+    } else {
+        find_hints(&sf.setup, limit)
+    }
+}
+
+pub fn render_board(setup: &Setup) -> String {
+    let mut builder = Builder::default();
+    builder.append(" 0123456789");
+    for i in setups::MIN_INDEX..=setups::MAX_INDEX {
+        builder.append('\n');
+        builder.append(i.to_string());
+        for j in setups::MIN_INDEX..=setups::MAX_INDEX {
+            if setup[i][j] > 0 {
+                builder.append('#')
+            } else {
+                builder.append(' ')
+            }
+        }
+    }
+    builder.string().unwrap()
+}
+
+#[derive(Clone)]
+pub struct State {
+    setups: Arc<Setups>,
+    ready: Arc<AtomicBool>,
+    stopping: Arc<AtomicBool>,
+    in_flight: Arc<AtomicUsize>,
+    storage: Arc<dyn Storage>,
+    battles: Arc<Mutex<im::HashMap<Uuid, battle::Battle>>>,
+    bus: Arc<Bus>,
+    admin_token: Option<String>,
+}
+impl State {
+    // a setup of the catalog or one assigned from the roster
+    pub fn setup(&self, id: &Uuid) -> Option<SetupFormat> {
+        match self.setups.get(id) {
+            Some(sf) => Some(*sf),
+            None => self.storage.roster().setup(&self.setups, id),
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct HintQuery {
+    #[serde(default)]
+    limit: u8,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct NewGameQuery {
+    player: Option<String>,
+    // the token a tournament team got on registration, to play for it
+    team_token: Option<String>,
+}
+
+pub async fn serve(app: Server<State>, addr: &str) -> tide::Result<()> {
+    let state = app.state().clone();
+    let mut listener = app.bind(addr).await?;
+    state.ready.store(true, Ordering::SeqCst);
+    listener.accept().or(shutdown::wait_for_signal()).await?;
+    drop(listener);
+
+    state.ready.store(false, Ordering::SeqCst);
+    state.stopping.store(true, Ordering::SeqCst);
+    if !shutdown::drain(&state, shutdown::deadline()).await {
+        eprintln!("Shutdown deadline reached with requests still in flight");
+    }
+    state.storage.flush()?;
+    Ok(())
+}
+
+pub fn build_app() -> Server<State> {
+    build_app_with(Arc::new(MemoryStorage::new()))
+}
+
+pub fn build_app_with(storage: Arc<dyn Storage>) -> Server<State> {
+    build_app_from(build_all(), storage, None)
+}
+
+// Without an admin token the admin routes answer 403 to everyone.
+pub fn build_app_from(
+    setups: Setups,
+    storage: Arc<dyn Storage>,
+    admin_token: Option<String>,
+) -> Server<State> {
+    let state: State = State {
+        setups: Arc::new(setups),
+        ready: Arc::new(AtomicBool::new(false)),
+        stopping: Arc::new(AtomicBool::new(false)),
+        in_flight: Arc::new(AtomicUsize::new(0)),
+        storage,
+        battles: Arc::new(Mutex::new(im::HashMap::new())),
+        bus: Arc::new(Bus::default()),
+        admin_token,
+    };
+    let mut app: Server<State> = tide::with_state(state);
+    app.with(shutdown::InFlight);
+    app.with(limits::BodyLimits);
+    let mut routes = Routes::new(&mut app);
+    // the unprefixed routes are aliases of v1
+    for prefix in ["", "/v1"] {
+        routes.version(prefix, true);
+        game_routes(&mut routes);
+        shared_routes(&mut routes);
+    }
+    routes.version("/v2", false);
+    v2::game_routes(&mut routes);
+    shared_routes(&mut routes);
+    routes.version("", false);
+    routes.get(
+        "/events",
+        events::events,
+        Doc::new("Stream game events, admin only")
+            .header("Authorization", true)
+            .response::<events::GameEvent>(200, CT_EVENT_STREAM)
+            .text(403),
+    );
+    routes.get(
+        "/daily",
+        daily::daily,
+        Doc::new("The setup of the day")
+            .wire_response::<NewGame>(200, false)
+            .wire_response::<NestedNewGame>(200, true)
+            .text(404),
+    );
+    routes.get(
+        "/daily/history",
+        daily::daily_history,
+        Doc::new("Setups of the past days, latest first")
+            .query::<daily::HistoryQuery>()
+            .response::<Vec<daily::DailyEntry>>(200, CT_YAML),
+    );
+    routes.post(
+        "/tournaments",
+        tournament::create,
+        Doc::new("Open a tournament, admin only")
+            .header("Authorization", true)
+            .body::<tournament::NewTournament>(CT_YAML)
+            .response::<tournament::TournamentStatus>(201, CT_YAML)
+            .text(403)
+            .text(415)
+            .text(422),
+    );
+    routes.get(
+        "/tournaments/:tournament_id",
+        tournament::show,
+        Doc::new("Rounds and live standings of a tournament")
+            .response::<tournament::TournamentStatus>(200, CT_YAML)
+            .text(404),
+    );
+    routes.post(
+        "/tournaments/:tournament_id/teams",
+        tournament::register,
+        Doc::new("Register a team, which plays as the player of that name with its token")
+            .body::<tournament::NewTeam>(CT_YAML)
+            .response::<tournament::RegisteredTeam>(201, CT_YAML)
+            .text(404)
+            .text(409)
+            .text(415)
+            .text(422),
+    );
+    routes.get(
+        "/tournaments/:tournament_id/replay",
+        tournament::show_replay,
+        Doc::new("Standings after every solve, replayed from the stored events")
+            .response::<Vec<tournament::ReplayFrame>>(200, CT_YAML)
+            .text(404),
+    );
+    routes.put(
+        "/roster",
+        roster::upload,
+        Doc::new("Assign a setup to every student, admin only")
+            .header("Authorization", true)
+            .body::<roster::RosterUpload>(CT_YAML)
+            .response::<Vec<roster::Assignment>>(200, CT_YAML)
+            .text(403)
+            .text(415),
+    );
+    routes.get(
+        "/roster",
+        roster::show,
+        Doc::new("Assignments and their progress, admin only")
+            .header("Authorization", true)
+            .response::<Vec<roster::AssignmentStatus>>(200, CT_YAML)
+            .text(403),
+    );
+    routes.get(
+        "/assignments/:student",
+        roster::assignment,
+        Doc::new("The game assigned to a student")
+            .wire_response::<NewGame>(200, false)
+            .wire_response::<NestedNewGame>(200, true)
+            .text(404),
+    );
+    routes.get("/healthz", health::healthz, Doc::new("Liveness probe").text(200));
+    routes.get(
+        "/readyz",
+        health::readyz,
+        Doc::new("Readiness probe").text(200).text(503),
+    );
+    routes.get(
+        "/version",
+        health::version,
+        Doc::new("Build information").response::<health::BuildInfo>(200, CT_YAML),
+    );
+    routes.finish();
+
+    app
+}
+
+fn game_routes(routes: &mut Routes) {
+    routes.post(
+        "/game/:setup_id",
+        new_game,
+        Doc::new("Start a game on a setup")
+            .query::<NewGameQuery>()
+            .wire_response::<NewGame>(201, false)
+            .wire_response::<NestedNewGame>(201, true)
+            .text(403)
+            .text(404),
+    );
+    routes.post(
+        "/game/:setup_id/check",
+        check,
+        Doc::new("Check a solution")
+            .header(SESSION_HEADER, false)
+            .wire_body::<Check>()
+            .styled_body::<ConsList>()
+            .styled_body::<RunLength>()
+            .styled_body::<Bitmap>()
+            .styled_body::<A1>()
+            .text(200)
+            .text(404)
+            .text(415),
+    );
+    routes.get(
+        "/game/:setup_id/hint",
+        make_hint,
+        Doc::new("Reveal ship cells")
+            .query::<HintQuery>()
+            .header(SESSION_HEADER, false)
+            .wire_response::<Hints>(200, false)
+            .styled_response::<ConsList>(200)
+            .styled_response::<RunLength>(200)
+            .styled_response::<Bitmap>(200)
+            .styled_response::<A1>(200)
+            .text(404),
+    );
+    routes.get(
+        "/game/:setup_id/board",
+        show_board,
+        Doc::new("Render the solved board").text(200).text(404),
+    );
+    routes.get(
+        "/game/:setup_id/explanation",
+        deduce::steps,
+        Doc::new("Explain how the setup is solved, step by step")
+            .query::<HintQuery>()
+            .response::<deduce::Explanation>(200, CT_YAML)
+            .text(404),
+    );
+    routes.post(
+        "/game/:setup_id/explanation/next",
+        deduce::next,
+        Doc::new("The next deduction from the marks made so far")
+            .header(SESSION_HEADER, false)
+            .body::<validate::PartialSolution>(CT_YAML)
+            .response::<deduce::Step>(200, CT_YAML)
+            .text(404)
+            .text(415)
+            .text(422),
+    );
+    routes.get(
+        "/game/:setup_id/explanation/boards",
+        deduce::boards,
+        Doc::new("Render the board after each deduction step")
+            .query::<HintQuery>()
+            .text(200)
+            .text(404),
+    );
+    routes.post(
+        "/game/:setup_id/validate",
+        validate::validate_partial,
+        Doc::new("Check a partial solution for contradictions")
+            .header(SESSION_HEADER, false)
+            .body::<validate::PartialSolution>(CT_YAML)
+            .response::<validate::Validation>(200, CT_YAML)
+            .text(404)
+            .text(415)
+            .text(422),
+    );
+}
+
+fn shared_routes(routes: &mut Routes) {
+    routes.get(
+        "/sessions/:session_id/live",
+        live::live,
+        Doc::new("Play a session over a WebSocket")
+            .websocket::<live::LiveCommand, live::LiveEvent>()
+            .text(404),
+    );
+    routes.get(
+        "/leaderboard",
+        leaderboard::global,
+        Doc::new("Global leaderboard").response::<Vec<leaderboard::GlobalEntry>>(200, CT_YAML),
+    );
+    routes.get(
+        "/leaderboard/:setup_id",
+        leaderboard::per_setup,
+        Doc::new("Leaderboard of a setup")
+            .response::<Vec<leaderboard::SetupEntry>>(200, CT_YAML)
+            .text(404),
+    );
+    routes.get(
+        "/players/:player_id/stats",
+        leaderboard::stats,
+        Doc::new("Statistics of a player")
+            .response::<leaderboard::PlayerStats>(200, CT_YAML)
+            .text(404),
+    );
+    routes.post(
+        "/battles",
+        battle::create,
+        Doc::new("Open a battle").response::<battle::BattleStatus>(201, CT_YAML),
+    );
+    routes.get(
+        "/battles/:battle_id",
+        battle::show,
+        Doc::new("Status of a battle")
+            .response::<battle::BattleStatus>(200, CT_YAML)
+            .text(404),
+    );
+    routes.post(
+        "/battles/:battle_id/players",
+        battle::register,
+        Doc::new("Join a battle with a fleet")
+            .body::<battle::Registration>(CT_YAML)
+            .response::<battle::Registered>(201, CT_YAML)
+            .text(404)
+            .text(409)
+            .text(415)
+            .text(422),
+    );
+    routes.post(
+        "/battles/:battle_id/shots",
+        battle::fire,
+        Doc::new("Fire a shot")
+            .body::<battle::Shot>(CT_YAML)
+            .response::<battle::ShotOutcome>(200, CT_YAML)
+            .text(404)
+            .text(409)
+            .text(415)
+            .text(422),
+    );
+}
+
+async fn show_board(req: Request<State>) -> tide::Result {
+    let game_setup_str: &str = req.param("setup_id")?;
+    if let Ok(game_setup_id) = Uuid::from_str(game_setup_str) {
+        if let Some(setup) = req.state().setup(&game_setup_id) {
+            text_response(&render_board(&setup.setup))
+        } else {
+            not_found("Unknown game setup")
+        }
+    } else {
+        not_found("Game setup id not found")
+    }
+}
+
+async fn make_hint(req: Request<State>) -> tide::Result {
+    let game_setup_str: &str = req.param("setup_id")?;
+    if let Ok(game_setup_id) = Uuid::from_str(game_setup_str) {
+        if let Some(setup) = req.state().setup(&game_setup_id) {
+            let hints: Vec<Coord> = find_hints(&setup.setup, hint_limit(&req));
+            record_hints(&req, game_setup_id, hints.len() as u8)?;
+            coords_response(200, &setup, &hints)
+        } else {
+            not_found("Unknown game setup")
+        }
+    } else {
+        not_found("Game setup id not found")
+    }
+}
+
+async fn check(mut req: Request<State>) -> tide::Result {
+    let game_setup_str: &str = req.param("setup_id")?;
+    if let Ok(game_setup_id) = Uuid::from_str(game_setup_str) {
+        if let Some(setup) = req.state().setup(&game_setup_id) {
+            let content_type = req.content_type().map(|ct| ct.to_string());
+            if let Ok(body) = req.body_bytes().await {
+                match decode_check(&setup, content_type.as_deref(), &body) {
+                    Ok(entity) => {
+                        let solved = entity.solves(setup.setup);
+                        record_check(&req, game_setup_id, solved)?;
+                        if solved {
+                            finish()
+                        } else {
+                            try_harder()
+                        }
+                    }
+                    Err(text) => illegal_request(&text),
+                }
+            } else {
+                illegal_request("Could not read the request")
+            }
+        } else {
+            not_found("Unknown game setup")
+        }
+    } else {
+        not_found("Game setup id not found")
+    }
+}
+
+async fn new_game(req: Request<State>) -> tide::Result {
+    let game_setup_str: &str = req.param("setup_id")?;
+    if let Ok(game_setup_id) = Uuid::from_str(game_setup_str) {
+        if let Some(sf) = req.state().setup(&game_setup_id) {
+            if let Some(session_id) = start_session(&req, game_setup_id)? {
+                let mut resp = game_response(201, game_setup_id, &sf)?;
+                resp.insert_header(SESSION_HEADER, session_id.to_string());
+                Ok(resp)
+            } else {
+                tournament::wrong_team()
+            }
+        } else {
+            not_found("Game template not found")
+        }
+    } else {
+        not_found("Game setup id not found")
+    }
+}
+
+// the new game payload in the create and wire format of the setup
+fn game_response(status: u16, game_setup_id: Uuid, sf: &SetupFormat) -> tide::Result {
+    let s = sf.setup;
+    match sf.create_format {
+        CreateFormat::Nested => {
+            let resp = NestedNewGame {
+                game_setup_id,
+                number_of_hints: number_of_hints(sf),
+                occupied_cols: to_non_empty_list(&mut s.occupied_cols().to_vec()),
+                occupied_rows: to_non_empty_list(&mut s.occupied_rows().to_vec()),
+            };
+            wire_response(status, sf.wire_format, true, &resp)
+        }
+        CreateFormat::List => {
+            let resp = NewGame {
+                game_setup_id,
+                number_of_hints: number_of_hints(sf),
+                occupied_cols: s.occupied_cols(),
+                occupied_rows: s.occupied_rows(),
+            };
+            wire_response(status, sf.wire_format, false, &resp)
+        }
+    }
+}
+
+// the size of the irreducible hint set, when the catalog knows it
+fn number_of_hints(sf: &SetupFormat) -> u8 {
+    match sf.irreducible_hints {
+        Some(hints) => hints.len(),
+        None => MAX_HINTS,
+    }
+}
+
+fn hint_limit(req: &Request<State>) -> u8 {
+    match req.query::<HintQuery>() {
+        Ok(v) => v.limit.min(MAX_HINTS),
+        Err(_) => 0,
+    }
+}
+
+// None when a team token is given that was not issued to the player.
+fn start_session(req: &Request<State>, game_setup_id: Uuid) -> tide::Result<Option<Uuid>> {
+    let (player, team_token) = match req.query::<NewGameQuery>() {
+        Ok(q) => (q.player.filter(|p| !p.is_empty()), q.team_token),
+        Err(_) => (None, None),
+    };
+    let team_token = match team_token {
+        Some(token) => match Uuid::from_str(&token) {
+            Ok(token) if player.is_some() && req.state().storage.team(&token) == player => {
+                Some(token)
+            }
+            _ => return Ok(None),
+        },
+        None => None,
+    };
+    let session_id = Uuid::new_v4();
+    req.state().storage.record(Event::SessionStarted {
+        session_id,
+        setup_id: game_setup_id,
+        player,
+        team_token,
+        at: now_millis(),
+    })?;
+    req.state().bus.publish(GameEvent::GameCreated {
+        setup_id: game_setup_id,
+        player_id: player_of(
+            req.state(),
+            req.state().storage.session(&session_id).as_ref(),
+        ),
+    });
+    Ok(Some(session_id))
+}
+
+fn record_hints(req: &Request<State>, game_setup_id: Uuid, hints: u8) -> tide::Result<()> {
+    let session = session_of(req, &game_setup_id);
+    if let Some(session) = &session {
+        req.state().storage.record(Event::HintServed {
+            session_id: session.id,
+            hints,
+            at: now_millis(),
+        })?;
+    }
+    req.state().bus.publish(GameEvent::HintServed {
+        setup_id: game_setup_id,
+        player_id: player_of(req.state(), session.as_ref()),
+        hints,
+    });
+    Ok(())
+}
+
+fn record_check(req: &Request<State>, game_setup_id: Uuid, solved: bool) -> tide::Result<()> {
+    let session = session_of(req, &game_setup_id);
+    if let Some(session) = &session {
+        req.state().storage.record(Event::CheckAttempted {
+            session_id: session.id,
+            solved,
+            at: now_millis(),
+        })?;
+    }
+    let player_id = player_of(req.state(), session.as_ref());
+    req.state().bus.publish(if solved {
+        GameEvent::CheckSolved {
+            setup_id: game_setup_id,
+            player_id,
+        }
+    } else {
+        GameEvent::CheckFailed {
+            setup_id: game_setup_id,
+            player_id,
+        }
+    });
+    Ok(())
+}
+
+fn session_of(req: &Request<State>, game_setup_id: &Uuid) -> Option<Session> {
+    let session_id = Uuid::from_str(req.header(SESSION_HEADER)?.last().as_str()).ok()?;
+    let session = req.state().storage.session(&session_id)?;
+    if session.setup_id == *game_setup_id {
+        Some(session)
+    } else {
+        None
+    }
+}
+
+fn player_of(state: &State, session: Option<&Session>) -> String {
+    match session {
+        Some(Session {
+            player: Some(player),
+            ..
+        }) => state.bus.anonymize(player),
+        Some(session) => state.bus.anonymize(&session.id.to_string()),
+        None => "anonymous".to_string(),
+    }
+}
+
+fn yaml_response<T>(status: u16, ct: &str, value: &T) -> tide::Result
+where
+    T: Serialize,
+{
+    let yaml: String = serde_yaml::to_string(&value)?;
+    Ok(Response::builder(status)
+        .body(yaml)
+        .content_type(ct)
+        .build())
+}
+
+fn wire_response<T>(status: u16, format: WireFormat, nested: bool, value: &T) -> tide::Result
+where
+    T: Serialize,
+{
+    let bytes = format.encode(&value)?;
+    Ok(Response::builder(status)
+        .body(bytes)
+        .content_type(format.content_type(nested))
+        .build())
+}
+
+fn coords_response(status: u16, sf: &SetupFormat, coords: &[Coord]) -> tide::Result {
+    let bytes = sf.encoding.encode_coords(sf.wire_format, coords)?;
+    Ok(Response::builder(status)
+        .body(bytes)
+        .content_type(sf.encoding.content_type(sf.wire_format).as_str())
+        .build())
+}
+
+// Decodes the style the setup declares, the list the content type stands for,
+// the nested list when games start nested and a flat list last, in the
+// setup's wire format. A content type naming
+// another format or an encoding parameter naming another style is refused.
+fn decode_check(
+    sf: &SetupFormat,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<Check, String> {
+    let mut styles = vec![sf.encoding];
+    if let Some(content_type) = content_type {
+        if let Some(name) = EncodingStyle::parameter(content_type) {
+            if EncodingStyle::from_name(name) != Some(sf.encoding) {
+                return Err(format!(
+                    "Encoding {} does not match the setup, use {}",
+                    name,
+                    sf.encoding.name()
+                ));
+            }
+        }
+        if let Some((format, style)) = EncodingStyle::from_content_type(content_type) {
+            if format != sf.wire_format {
+                return Err(format!(
+                    "Content type {} does not match the setup, use {}",
+                    content_type,
+                    sf.wire_format.content_type(false)
+                ));
+            }
+            if style != sf.encoding {
+                styles.push(style);
+            }
+        }
+    }
+    if sf.create_format == CreateFormat::Nested {
+        styles.push(EncodingStyle::ConsList);
+    }
+    styles.push(EncodingStyle::FlatList);
+    styles
+        .iter()
+        .find_map(|style| style.decode_coords(sf.wire_format, body).ok())
+        .map(|coords| Check { coords })
+        .ok_or_else(|| "Could not parse entity".to_string())
+}
+
+fn illegal_request(text: &str) -> tide::Result {
+    Ok(Response::builder(415)
+        .body(text)
+        .content_type("text/plain")
+        .build())
+}
+
+fn conflict(text: &str) -> tide::Result {
+    Ok(Response::builder(409)
+        .body(text)
+        .content_type(CT_PLAIN)
+        .build())
+}
+
+fn unprocessable(text: &str) -> tide::Result {
+    Ok(Response::builder(422)
+        .body(text)
+        .content_type(CT_PLAIN)
+        .build())
+}
+
+fn not_found(text: &str) -> tide::Result {
+    Ok(Response::builder(404)
+        .body(text)
+        .content_type(CT_PLAIN)
+        .build())
+}
+
+fn text_response(text: &str) -> tide::Result {
+    Ok(Response::builder(200)
+        .body(text)
+        .content_type(CT_PLAIN)
+        .build())
+}
+
+fn finish() -> tide::Result {
+    text_response("Well done!")
+}
+
+fn try_harder() -> tide::Result {
+    text_response("Try harder!")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bimaru_protocol::{NestedHints, CT_YAML_NESTED};
+    use storage::FileStorage;
+    use tide_testing::TideTestingExt;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn prop_check(
+            setup in setups::arb_setup(),
+            cell in 0..100usize,
+            k in 0..20usize,
+            w in 0..80usize,
+        ) {
+            let ships = setups::cells(&setup);
+            prop_assert!(Check::from_cells(&ships).solves(setup));
+
+            // one cell toggled
+            let cell = (cell / 10, cell % 10);
+            let mut toggled: Vec<(usize, usize)> =
+                ships.iter().copied().filter(|c| *c != cell).collect();
+            if toggled.len() == ships.len() {
+                toggled.push(cell);
+            }
+            prop_assert!(!Check::from_cells(&toggled).solves(setup));
+
+            // one ship cell moved to the water
+            let water: Vec<(usize, usize)> = (0..100)
+                .map(|i| (i / 10, i % 10))
+                .filter(|c| !ships.contains(c))
+                .collect();
+            let mut moved = ships.clone();
+            moved[k] = water[w];
+            prop_assert!(!Check::from_cells(&moved).solves(setup));
+        }
+    }
+
+    #[async_std::test]
+    async fn test_create_and_check() {
+        let game_setup_id = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+        let app = build_app();
+        let create_resp = app
+            .post(format!("/game/{}", game_setup_id))
+            .recv_string()
+            .await
+            .unwrap();
+        let create_entity = serde_yaml::from_str::<NewGame>(&create_resp).unwrap();
+        // the size of the irreducible hint set of the setup
+        assert_eq!(create_entity.number_of_hints, 6);
+        assert_eq!(
+            create_entity.occupied_rows.len() + create_entity.occupied_cols.len(),
+            20
+        );
+
+        let check_v1_entity = Check {
+            coords: vec![Coord { col: 0, row: 0 }, Coord { col: 1, row: 1 }],
+        };
+        let check_v1_str = serde_yaml::to_string(&check_v1_entity).unwrap();
+        let check_v1_resp = app
+            .post(format!("/game/{}/check", game_setup_id))
+            .body_string(check_v1_str)
+            .recv_string()
+            .await
+            .unwrap();
+        assert_eq!(check_v1_resp, "Try harder!");
+
+        let check_v1_entity = Check {
+            coords: vec![
+                Coord { col: 0, row: 1 },
+                Coord { col: 0, row: 4 },
+                Coord { col: 2, row: 3 },
+                Coord { col: 3, row: 6 },
+                Coord { col: 3, row: 7 },
+                Coord { col: 3, row: 8 },
+                Coord { col: 5, row: 0 },
+                Coord { col: 5, row: 2 },
+                Coord { col: 5, row: 3 },
+                Coord { col: 5, row: 4 },
+                Coord { col: 5, row: 6 },
+                Coord { col: 6, row: 0 },
+                Coord { col: 7, row: 0 },
+                Coord { col: 7, row: 7 },
+                Coord { col: 7, row: 9 },
+                Coord { col: 8, row: 0 },
+                Coord { col: 8, row: 7 },
+                Coord { col: 8, row: 9 },
+                Coord { col: 9, row: 4 },
+                Coord { col: 9, row: 5 },
+            ],
+        };
+        let check_v1_str = serde_yaml::to_string(&check_v1_entity).unwrap();
+        let check_v1_resp = app
+            .post(format!("/game/{}/check", game_setup_id))
+            .body_string(check_v1_str)
+            .recv_string()
+            .await
+            .unwrap();
+        assert_eq!(check_v1_resp, "Well done!");
+    }
+
+    #[async_std::test]
+    async fn test_probes() {
+        let app = build_app();
+        let health_resp = app.get("/healthz").await.unwrap();
+        assert_eq!(health_resp.status(), 200);
+
+        let not_ready_resp = app.get("/readyz").await.unwrap();
+        assert_eq!(not_ready_resp.status(), 503);
+        app.state().ready.store(true, Ordering::SeqCst);
+        let ready_resp = app.get("/readyz").await.unwrap();
+        assert_eq!(ready_resp.status(), 200);
+
+        // a setup with the wrong fleet is not a validated catalog
+        let mut setups = build_all();
+        let id = *setups.keys().next().unwrap();
+        setups[&id].setup = setups::two_battleships();
+        let wrong = build_app_from(setups, Arc::new(MemoryStorage::new()), None);
+        wrong.state().ready.store(true, Ordering::SeqCst);
+        let mut wrong_resp = wrong.get("/readyz").await.unwrap();
+        assert_eq!(wrong_resp.status(), 503);
+        assert_eq!(
+            wrong_resp.body_string().await.unwrap(),
+            "Catalog contains invalid setups"
+        );
+
+        let version_resp = app.get("/version").recv_string().await.unwrap();
+        assert!(version_resp.contains(env!("CARGO_PKG_VERSION")));
+    }
+
+    #[async_std::test]
+    async fn test_stopping() {
+        let app = build_app();
+        app.state().ready.store(true, Ordering::SeqCst);
+        app.state().stopping.store(true, Ordering::SeqCst);
+        let resp = app.get("/leaderboard").await.unwrap();
+        assert_eq!(resp.status(), 503);
+        // the probes still answer
+        let resp = app.get("/healthz").await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let mut resp = app.get("/readyz").await.unwrap();
+        assert_eq!(resp.status(), 503);
+        assert_eq!(resp.body_string().await.unwrap(), "Server is shutting down");
+        assert_eq!(app.state().in_flight.load(Ordering::SeqCst), 0);
+        assert!(shutdown::drain(app.state(), std::time::Duration::ZERO).await);
+
+        app.state().in_flight.fetch_add(1, Ordering::SeqCst);
+        assert!(!shutdown::drain(app.state(), std::time::Duration::ZERO).await);
+    }
+
+    #[async_std::test]
+    async fn test_sessions_survive_restart() {
+        let game_setup_id = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+        let path = storage::temp_path("restart");
+
+        let app = build_app_with(Arc::new(FileStorage::open(&path).unwrap()));
+        let create_resp = app.post(format!("/game/{}", game_setup_id)).await.unwrap();
+        let session_id = create_resp
+            .header(SESSION_HEADER)
+            .unwrap()
+            .last()
+            .to_string();
+        drop(app);
+
+        let app = build_app_with(Arc::new(FileStorage::open(&path).unwrap()));
+        app.get(format!("/game/{}/hint?limit=4", game_setup_id))
+            .header(SESSION_HEADER, session_id.as_str())
+            .await
+            .unwrap();
+        drop(app);
+
+        let app = build_app_with(Arc::new(FileStorage::open(&path).unwrap()));
+        let setup = build_all().get(&game_setup_id).unwrap().setup;
+        let check_resp = app
+            .post(format!("/game/{}/check", game_setup_id))
+            .header(SESSION_HEADER, session_id.as_str())
+            .body_string(serde_yaml::to_string(&Check::from_cells(&setups::cells(&setup))).unwrap())
+            .recv_string()
+            .await
+            .unwrap();
+        assert_eq!(check_resp, "Well done!");
+        drop(app);
+
+        let storage = FileStorage::open(&path).unwrap();
+        let session = storage
+            .session(&Uuid::from_str(&session_id).unwrap())
+            .unwrap();
+        assert_eq!(session.setup_id, game_setup_id);
+        assert_eq!(session.hints_used, 4);
+        assert_eq!(session.check_attempts, 1);
+        assert!(session.completed_at.is_some());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[async_std::test]
+    async fn test_leaderboard() {
+        let game_setup_id = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+        let app = build_app();
+        let create_resp = app
+            .post(format!("/game/{}?player=alice", game_setup_id))
+            .await
+            .unwrap();
+        let session_id = create_resp
+            .header(SESSION_HEADER)
+            .unwrap()
+            .last()
+            .to_string();
+        app.get(format!("/game/{}/hint?limit=2", game_setup_id))
+            .header(SESSION_HEADER, session_id.as_str())
+            .await
+            .unwrap();
+        let setup = build_all().get(&game_setup_id).unwrap().setup;
+        app.post(format!("/game/{}/check", game_setup_id))
+            .header(SESSION_HEADER, session_id.as_str())
+            .body_string(serde_yaml::to_string(&Check::from_cells(&setups::cells(&setup))).unwrap())
+            .await
+            .unwrap();
+
+        let board_resp = app
+            .get(format!("/leaderboard/{}", game_setup_id))
+            .recv_string()
+            .await
+            .unwrap();
+        let board = serde_yaml::from_str::<Vec<leaderboard::SetupEntry>>(&board_resp).unwrap();
+        assert_eq!(board.len(), 1);
+        assert_eq!(board[0].player, "alice");
+        assert_eq!(board[0].hints_used, 2);
+
+        let global_resp = app.get("/leaderboard").recv_string().await.unwrap();
+        let global = serde_yaml::from_str::<Vec<leaderboard::GlobalEntry>>(&global_resp).unwrap();
+        assert_eq!(global.len(), 1);
+        assert_eq!(global[0].setups_solved, 1);
+
+        let stats_resp = app.get("/players/alice/stats").recv_string().await.unwrap();
+        let stats = serde_yaml::from_str::<leaderboard::PlayerStats>(&stats_resp).unwrap();
+        assert_eq!(stats.games_solved, 1);
+        assert_eq!(stats.check_attempts, 1);
+
+        let unknown_resp = app.get("/players/bob/stats").await.unwrap();
+        assert_eq!(unknown_resp.status(), 404);
+    }
+
+    #[async_std::test]
+    async fn test_events() {
+        let game_setup_id = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+        let app = build_app();
+        let forbidden_resp = app.get("/events").await.unwrap();
+        assert_eq!(forbidden_resp.status(), 403);
+
+        let receiver = app.state().bus.subscribe();
+        let create_resp = app
+            .post(format!("/game/{}?player=alice", game_setup_id))
+            .await
+            .unwrap();
+        let session_id = create_resp
+            .header(SESSION_HEADER)
+            .unwrap()
+            .last()
+            .to_string();
+        app.get(format!("/game/{}/hint?limit=2", game_setup_id))
+            .header(SESSION_HEADER, session_id.as_str())
+            .await
+            .unwrap();
+        app.post(format!("/game/{}/check", game_setup_id))
+            .body_string("coords: []".to_string())
+            .await
+            .unwrap();
+
+        let player_id = app.state().bus.anonymize("alice");
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            GameEvent::GameCreated {
+                setup_id: game_setup_id,
+                player_id: player_id.clone()
+            }
+        );
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            GameEvent::HintServed {
+                setup_id: game_setup_id,
+                player_id,
+                hints: 2
+            }
+        );
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            GameEvent::CheckFailed {
+                setup_id: game_setup_id,
+                player_id: "anonymous".to_string()
+            }
+        );
+    }
+
+    #[async_std::test]
+    async fn test_validate_partial() {
+        let game_setup_id = uuid::uuid!("37073150-f43d-4609-94ec-dcbeffcb472a");
+        let app = build_app();
+        let create_resp = app
+            .post(format!("/game/{}", game_setup_id))
+            .await
+            .unwrap();
+        let session_id = create_resp
+            .header(SESSION_HEADER)
+            .unwrap()
+            .last()
+            .to_string();
+        let body = "water:\n- col: 5\n  row: 6\n".to_string();
+
+        let validate_resp = app
+            .post(format!("/game/{}/validate", game_setup_id))
+            .header(SESSION_HEADER, session_id.as_str())
+            .body_string(body.clone())
+            .recv_string()
+            .await
+            .unwrap();
+        let validation = serde_yaml::from_str::<validate::Validation>(&validate_resp).unwrap();
+        assert!(validation.consistent);
+
+        app.get(format!("/game/{}/hint?limit=2", game_setup_id))
+            .header(SESSION_HEADER, session_id.as_str())
+            .await
+            .unwrap();
+        let validate_resp = app
+            .post(format!("/game/{}/validate", game_setup_id))
+            .header(SESSION_HEADER, session_id.as_str())
+            .body_string(body)
+            .recv_string()
+            .await
+            .unwrap();
+        let validation = serde_yaml::from_str::<validate::Validation>(&validate_resp).unwrap();
+        assert!(!validation.consistent);
+        assert_eq!(validation.contradicted_hints, vec![Coord { row: 6, col: 5 }]);
+
+        let off_board_resp = app
+            .post(format!("/game/{}/validate", game_setup_id))
+            .body_string("ships:\n- col: 10\n  row: 0\n".to_string())
+            .await
+            .unwrap();
+        assert_eq!(off_board_resp.status(), 422);
+    }
+
+    #[async_std::test]
+    async fn test_hints() {
+        let game_setup_id = uuid::uuid!("37073150-f43d-4609-94ec-dcbeffcb472a");
+        let app = build_app();
+        let hint_resp = app
+            .get(format!("/game/{}/hint?limit=3", game_setup_id))
+            .recv_string()
+            .await
+            .unwrap();
+        let hint_entity = serde_yaml::from_str::<Hints>(&hint_resp).unwrap();
+        assert_eq!(hint_entity.coords.len(), 3);
+        assert_eq!(
+            hint_entity,
+            Hints {
+                coords: vec![
+                    Coord { row: 6, col: 4 },
+                    Coord { row: 6, col: 5 },
+                    Coord { row: 6, col: 6 }
+                ]
+            }
+        );
+    }
+    #[async_std::test]
+    async fn test_create_nested() {
+        let game_setup_id = uuid::uuid!("37073150-f43d-4609-94ec-dcbeffcb472a");
+        let app = build_app();
+        let create_resp = app
+            .post(format!("/game/{}", game_setup_id))
+            .recv_string()
+            .await
+            .unwrap();
+        let create_entity = serde_yaml::from_str::<NestedNewGame>(&create_resp).unwrap();
+        assert_eq!(create_entity.number_of_hints, 5);
+        assert_eq!(create_entity.occupied_cols.unwrap().head, 0);
+        assert_eq!(create_entity.occupied_rows.unwrap().head, 1);
+    }
+
+    #[async_std::test]
+    async fn test_hints_nested() {
+        let game_setup_id = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+        let app = build_app();
+        let hint_resp = app
+            .get(format!("/game/{}/hint?limit=3", game_setup_id))
+            .recv_string()
+            .await
+            .unwrap();
+        let hint_entity = serde_yaml::from_str::<NestedHints>(&hint_resp).unwrap();
+        assert_eq!(
+            hint_entity,
+            NestedHints {
+                coords: Some(NonEmptyList {
+                    head: Coord { row: 0, col: 8 },
+                    tail: Some(Box::new(NonEmptyList {
+                        head: Coord { row: 0, col: 7 },
+                        tail: Some(Box::new(NonEmptyList {
+                            head: Coord { row: 0, col: 6 },
+                            tail: None
+                        }))
+                    }))
+                })
+            }
+        );
+    }
+
+    #[async_std::test]
+    async fn test_client_round_trip() {
+        let app = build_app();
+        let client = bimaru_protocol::Client::from(app.client());
+        for game_setup_id in [
+            uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b"),
+            uuid::uuid!("37073150-f43d-4609-94ec-dcbeffcb472a"),
+        ] {
+            let setup = app.state().setups.get(&game_setup_id).unwrap().setup;
+            let game = client
+                .create_game(&game_setup_id, Some("bob"))
+                .await
+                .unwrap();
+            assert_eq!(game.game_setup_id, game_setup_id);
+            let sf = app.state().setup(&game_setup_id).unwrap();
+            assert_eq!(game.number_of_hints, number_of_hints(&sf));
+            assert_eq!(game.occupied_rows, setup.occupied_rows().to_vec());
+            assert_eq!(game.occupied_cols, setup.occupied_cols().to_vec());
+
+            let hints = client.hint(&game, 3).await.unwrap();
+            assert_eq!(hints, find_hints(&setup, 3));
+            let board = client.board(&game_setup_id).await.unwrap();
+            assert_eq!(board, render_board(&setup));
+
+            let partial = Check { coords: hints };
+            assert!(!client.check(&game, &partial).await.unwrap());
+            let solution = Check::from_cells(&setups::cells(&setup));
+            assert!(client.check(&game, &solution).await.unwrap());
+
+            let session = app
+                .state()
+                .storage
+                .session(&game.session_id.unwrap())
+                .unwrap();
+            assert_eq!(session.player, Some("bob".to_string()));
+            assert_eq!(session.hints_used, 3);
+            assert!(session.completed_at.is_some());
+        }
+        let unknown = client.board(&Uuid::new_v4()).await.unwrap_err();
+        assert_eq!(unknown.status(), 404);
+    }
+
+    #[async_std::test]
+    async fn test_wire_formats() {
+        let list = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+        let nested = uuid::uuid!("37073150-f43d-4609-94ec-dcbeffcb472a");
+        for format in WireFormat::ALL {
+            let setups: Setups = build_all()
+                .into_iter()
+                .map(|(id, sf)| {
+                    (
+                        id,
+                        setups::SetupFormat {
+                            wire_format: format,
+                            ..sf
+                        },
+                    )
+                })
+                .collect();
+            let app = build_app_from(setups, Arc::new(MemoryStorage::new()), None);
+            let client = bimaru_protocol::Client::from(app.client());
+            for game_setup_id in [list, nested] {
+                let setup = app.state().setups.get(&game_setup_id).unwrap().setup;
+                let game = client.create_game(&game_setup_id, None).await.unwrap();
+                assert_eq!(game.wire_format, format);
+                assert_eq!(game.occupied_rows, setup.occupied_rows().to_vec());
+                let hints = client.hint(&game, 4).await.unwrap();
+                assert_eq!(hints, find_hints(&setup, 4));
+                let solution = Check::from_cells(&setups::cells(&setup));
+                assert!(client.check(&game, &solution).await.unwrap());
+            }
+
+            let resp = app.post(format!("/game/{}", nested)).await.unwrap();
+            assert_eq!(
+                resp.content_type().unwrap().essence(),
+                format.content_type(true)
+            );
+            // a body in another format is rejected
+            let other = if format == WireFormat::Cbor {
+                WireFormat::Json
+            } else {
+                WireFormat::Cbor
+            };
+            let resp = app
+                .post(format!("/game/{}/check", nested))
+                .body_bytes(other.encode(&Check { coords: vec![] }).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 415);
+
+            // so is the setup's own body under the content type of another format
+            let solution = Check::from_cells(&setups::cells(&build_all()[&nested].setup));
+            let resp = app
+                .post(format!("/game/{}/check", nested))
+                .body_bytes(format.encode(&solution).unwrap())
+                .content_type(other.content_type(false))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 415);
+        }
+    }
+
+    #[async_std::test]
+    async fn test_encoding_styles() {
+        let id = uuid::uuid!("37073150-f43d-4609-94ec-dcbeffcb472a");
+        for encoding in EncodingStyle::ALL {
+            let setups: Setups = build_all()
+                .into_iter()
+                .map(|(id, sf)| (id, setups::SetupFormat { encoding, ..sf }))
+                .collect();
+            let app = build_app_from(setups, Arc::new(MemoryStorage::new()), None);
+            let setup = app.state().setups.get(&id).unwrap().setup;
+            let client = bimaru_protocol::Client::from(app.client());
+            let game = client.create_game(&id, None).await.unwrap();
+            let mut hints = client.hint(&game, 4).await.unwrap();
+            let mut expected = find_hints(&setup, 4);
+            hints.sort_by_key(|c| (c.row, c.col));
+            expected.sort_by_key(|c| (c.row, c.col));
+            assert_eq!(hints, expected);
+
+            // the declared style is accepted, and the client sends a flat list
+            // under its content type
+            let cells: Vec<Coord> = Check::from_cells(&setups::cells(&setup)).coords;
+            let body = encoding.encode_coords(WireFormat::Yaml, &cells).unwrap();
+            let answer = app
+                .post(format!("/game/{}/check", id))
+                .body_bytes(body)
+                .recv_string()
+                .await
+                .unwrap();
+            assert_eq!(answer, "Well done!");
+
+            // a flat list is always accepted, whatever else the content type says
+            let flat = serde_yaml::to_string(&Check {
+                coords: cells.clone(),
+            })
+            .unwrap();
+            for content_type in ["text/plain", "application/x-yaml"] {
+                let answer = app
+                    .post(format!("/game/{}/check", id))
+                    .body_string(flat.clone())
+                    .content_type(content_type)
+                    .recv_string()
+                    .await
+                    .unwrap();
+                assert_eq!(answer, "Well done!", "{}", content_type);
+            }
+            let solution = Check { coords: cells };
+            assert!(client.check(&game, &solution).await.unwrap());
+        }
+    }
+
+    #[async_std::test]
+    async fn test_nested_check() {
+        // games start nested but hints are flat
+        let id = "37073150-f43d-4609-94ec-dcbeffcb472a";
+        let app = build_app();
+        let setup = app
+            .state()
+            .setups
+            .get(&Uuid::from_str(id).unwrap())
+            .unwrap();
+        let cells = Check::from_cells(&setups::cells(&setup.setup)).coords;
+        let nested = EncodingStyle::ConsList
+            .encode_coords(WireFormat::Yaml, &cells)
+            .unwrap();
+        let answer = app
+            .post(format!("/game/{}/check", id))
+            .body_bytes(nested.clone())
+            .content_type(CT_YAML_NESTED)
+            .recv_string()
+            .await
+            .unwrap();
+        assert_eq!(answer, "Well done!");
+
+        // the nested list is accepted whatever the content type says
+        for content_type in [CT_YAML, "text/plain"] {
+            let answer = app
+                .post(format!("/game/{}/check", id))
+                .body_bytes(nested.clone())
+                .content_type(content_type)
+                .recv_string()
+                .await
+                .unwrap();
+            assert_eq!(answer, "Well done!", "{}", content_type);
+        }
+        let answer = app
+            .post(format!("/game/{}/check", id))
+            .body_bytes(nested)
+            .recv_string()
+            .await
+            .unwrap();
+        assert_eq!(answer, "Well done!");
+
+        // a style the setup does not declare, even when it is named
+        let style = EncodingStyle::Bitmap;
+        let resp = app
+            .post(format!("/game/{}/check", id))
+            .body_bytes(style.encode_coords(WireFormat::Yaml, &cells).unwrap())
+            .content_type(style.content_type(WireFormat::Yaml).as_str())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 415);
+
+        let mut hostile = "coords: ".to_string();
+        hostile.push_str(&"{head: {col: 0, row: 0}, tail: ".repeat(1_000));
+        hostile.push_str(&"}".repeat(1_000));
+        let resp = app
+            .post(format!("/game/{}/check", id))
+            .body_string(hostile)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 415);
+    }
+}
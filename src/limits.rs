@@ -0,0 +1,96 @@
+use crate::{illegal_request, State, CT_PLAIN};
+use bimaru_protocol::{check_nesting, EncodingStyle, WireFormat};
+use futures_lite::AsyncReadExt;
+use tide::{Middleware, Next, Request, Response};
+
+// A check of a full board in any format fits many times over.
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+fn too_large() -> tide::Result {
+    Ok(Response::builder(413)
+        .body(format!(
+            "Request body is larger than {} bytes",
+            MAX_BODY_BYTES
+        ))
+        .content_type(CT_PLAIN)
+        .build())
+}
+
+// CBOR and MessagePack bodies are not text and bound their nesting themselves.
+fn is_binary(req: &Request<State>) -> bool {
+    let format = req
+        .content_type()
+        .and_then(|ct| EncodingStyle::from_content_type(&ct.to_string()))
+        .map(|(format, _)| format);
+    matches!(format, Some(WireFormat::Cbor | WireFormat::MessagePack))
+}
+
+// Reads the body up front so no handler sees more than MAX_BODY_BYTES or text
+// nested deeper than the wire formats accept.
+pub struct BodyLimits;
+
+#[tide::utils::async_trait]
+impl Middleware<State> for BodyLimits {
+    async fn handle(&self, mut req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        if req.is_empty() == Some(true) {
+            return Ok(next.run(req).await);
+        }
+        if req.len().is_some_and(|len| len > MAX_BODY_BYTES) {
+            return too_large();
+        }
+        let mut body = vec![];
+        if req
+            .take_body()
+            .take(MAX_BODY_BYTES as u64 + 1)
+            .read_to_end(&mut body)
+            .await
+            .is_err()
+        {
+            return illegal_request("Could not read the request");
+        }
+        if body.len() > MAX_BODY_BYTES {
+            return too_large();
+        }
+        if !is_binary(&req) && std::str::from_utf8(&body).is_ok() {
+            if let Err(e) = check_nesting(&body) {
+                return illegal_request(&format!("Could not parse entity: {}", e));
+            }
+        }
+        req.set_body(body);
+        Ok(next.run(req).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_app;
+    use tide_testing::TideTestingExt;
+
+    #[async_std::test]
+    async fn test_body_limits() {
+        let app = build_app();
+        let check = "/game/dd8fb490-72c8-485b-aeea-537b9be34e4b/check";
+        let resp = app
+            .post(check)
+            .body_string(format!("coords: []\n#{}", "x".repeat(MAX_BODY_BYTES)))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 413);
+
+        // too deep for every text format, even before the handler decodes it
+        for route in [check, "/tournaments", "/battles"] {
+            let resp = app.post(route).body_string("[".repeat(200)).await.unwrap();
+            assert_eq!(resp.status(), 415, "{}", route);
+        }
+
+        // the limits leave ordinary bodies to the handlers
+        let resp = app
+            .post(check)
+            .body_string("coords: []".to_string())
+            .recv_string()
+            .await
+            .unwrap();
+        assert_eq!(resp, "Try harder!");
+    }
+}
@@ -0,0 +1,313 @@
+use crate::limits::MAX_BODY_BYTES;
+use crate::setups::{OccupiedCells, Setup};
+use crate::storage::{now_millis, Event};
+use crate::{find_hints, not_found, shutdown, Coord, State, MAX_HINTS};
+use async_std::stream::StreamExt;
+use futures_lite::FutureExt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tide::{Endpoint, Request};
+use tide_websockets::{Message, WebSocket, WebSocketConnection};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CellMark {
+    Unknown,
+    Water,
+    Ship,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum LiveCommand {
+    Mark { coord: Coord, state: CellMark },
+    Hint { limit: u8 },
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LiveEvent {
+    Marked { coord: Coord, state: CellMark },
+    RowSatisfied { row: usize },
+    ColSatisfied { col: usize },
+    HintGranted { coords: Vec<Coord> },
+    Solved,
+    Error { message: String },
+}
+
+pub struct LiveBoard {
+    setup: Setup,
+    rows: [u8; 10],
+    cols: [u8; 10],
+    marks: [[CellMark; 10]; 10],
+    solved: bool,
+}
+
+impl LiveBoard {
+    pub fn new(setup: Setup) -> LiveBoard {
+        LiveBoard {
+            setup,
+            rows: setup.occupied_rows(),
+            cols: setup.occupied_cols(),
+            marks: [[CellMark::Unknown; 10]; 10],
+            solved: false,
+        }
+    }
+
+    fn ships_in_row(&self, row: usize) -> u8 {
+        self.marks[row]
+            .iter()
+            .filter(|m| **m == CellMark::Ship)
+            .count() as u8
+    }
+
+    fn ships_in_col(&self, col: usize) -> u8 {
+        self.marks
+            .iter()
+            .filter(|r| r[col] == CellMark::Ship)
+            .count() as u8
+    }
+
+    fn is_solved(&self) -> bool {
+        let mut ships = 0;
+        for (i, row) in self.marks.iter().enumerate() {
+            for (j, mark) in row.iter().enumerate() {
+                if (*mark == CellMark::Ship) != (self.setup[i][j] > 0) {
+                    return false;
+                }
+                if *mark == CellMark::Ship {
+                    ships += 1;
+                }
+            }
+        }
+        ships == 20
+    }
+
+    pub fn handle(&mut self, command: LiveCommand) -> Vec<LiveEvent> {
+        match command {
+            LiveCommand::Mark { coord, state } => {
+                if coord.row > 9 || coord.col > 9 {
+                    return vec![LiveEvent::Error {
+                        message: "Coordinate is off the board".to_string(),
+                    }];
+                }
+                let row_before = self.ships_in_row(coord.row) == self.rows[coord.row];
+                let col_before = self.ships_in_col(coord.col) == self.cols[coord.col];
+                self.marks[coord.row][coord.col] = state;
+                let mut events = vec![LiveEvent::Marked { coord, state }];
+                if !row_before && self.ships_in_row(coord.row) == self.rows[coord.row] {
+                    events.push(LiveEvent::RowSatisfied { row: coord.row });
+                }
+                if !col_before && self.ships_in_col(coord.col) == self.cols[coord.col] {
+                    events.push(LiveEvent::ColSatisfied { col: coord.col });
+                }
+                if !self.solved && self.is_solved() {
+                    self.solved = true;
+                    events.push(LiveEvent::Solved);
+                }
+                events
+            }
+            LiveCommand::Hint { limit } => vec![LiveEvent::HintGranted {
+                coords: find_hints(&self.setup, limit.min(MAX_HINTS)),
+            }],
+        }
+    }
+}
+
+// Messages are held to the limits of request bodies.
+pub fn parse_command(input: &str) -> Result<LiveCommand, String> {
+    if input.len() > MAX_BODY_BYTES {
+        return Err(format!("Message is larger than {} bytes", MAX_BODY_BYTES));
+    }
+    serde_yaml::from_str::<LiveCommand>(input).map_err(|e| e.to_string())
+}
+
+pub async fn live(req: Request<State>) -> tide::Result {
+    let session_str: &str = req.param("session_id")?;
+    if let Ok(session_id) = Uuid::from_str(session_str) {
+        if req.state().storage.session(&session_id).is_some() {
+            WebSocket::new(play).call(req).await
+        } else {
+            not_found("Unknown game session")
+        }
+    } else {
+        not_found("Game session id not found")
+    }
+}
+
+async fn play(req: Request<State>, mut stream: WebSocketConnection) -> tide::Result<()> {
+    let session_id = Uuid::from_str(req.param("session_id")?)?;
+    let state = req.state();
+    let session = state
+        .storage
+        .session(&session_id)
+        .ok_or_else(|| tide::Error::from_str(404, "Unknown game session"))?;
+    let setup = match state.setup(&session.setup_id) {
+        Some(sf) => sf.setup,
+        None => return Ok(()),
+    };
+    let mut board = LiveBoard::new(setup);
+    let stopped = || async {
+        shutdown::stopped(state).await;
+        None
+    };
+    while let Some(message) = stream.next().or(stopped()).await {
+        // pings are answered by the connection and other frames do not end
+        // the game, a broken read does
+        let input = match message {
+            Ok(Message::Text(input)) => input,
+            Ok(Message::Ping(_) | Message::Pong(_) | Message::Binary(_)) => continue,
+            Ok(Message::Close(_)) | Err(_) => break,
+        };
+        let events = match parse_command(&input) {
+            Ok(command) => board.handle(command),
+            Err(message) => vec![LiveEvent::Error { message }],
+        };
+        for event in events {
+            match event {
+                LiveEvent::HintGranted { ref coords } => {
+                    state.storage.record(Event::HintServed {
+                        session_id,
+                        hints: coords.len() as u8,
+                        at: now_millis(),
+                    })?
+                }
+                LiveEvent::Solved => state.storage.record(Event::CheckAttempted {
+                    session_id,
+                    solved: true,
+                    at: now_millis(),
+                })?,
+                _ => (),
+            }
+            stream.send_string(serde_yaml::to_string(&event)?).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setups::{build_all, GAME_2};
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use tide::http::upgrade::Connection;
+    use tide::listener::Listener;
+    use tide_testing::TideTestingExt;
+
+    fn mark(row: usize, col: usize, state: CellMark) -> LiveCommand {
+        LiveCommand::Mark {
+            coord: Coord { row, col },
+            state,
+        }
+    }
+
+    #[test]
+    fn test_live_board() {
+        let mut board = LiveBoard::new(GAME_2);
+        assert_eq!(
+            board.handle(mark(0, 8, CellMark::Ship)),
+            vec![
+                LiveEvent::Marked {
+                    coord: Coord { row: 0, col: 8 },
+                    state: CellMark::Ship
+                },
+                LiveEvent::RowSatisfied { row: 0 },
+            ]
+        );
+        assert_eq!(board.handle(mark(0, 8, CellMark::Water)).len(), 1);
+        assert_eq!(
+            board.handle(LiveCommand::Hint { limit: 20 }),
+            vec![LiveEvent::HintGranted {
+                coords: find_hints(&GAME_2, MAX_HINTS)
+            }]
+        );
+
+        let mut last = vec![];
+        for (row, cells) in GAME_2.iter().enumerate() {
+            for (col, value) in cells.iter().enumerate() {
+                if *value > 0 {
+                    last = board.handle(mark(row, col, CellMark::Ship));
+                }
+            }
+        }
+        assert_eq!(last.last(), Some(&LiveEvent::Solved));
+        assert!(!board
+            .handle(mark(9, 9, CellMark::Water))
+            .contains(&LiveEvent::Solved));
+    }
+
+    #[async_std::test]
+    async fn test_live_route() {
+        let app = crate::build_app();
+        let id = build_all()
+            .iter()
+            .find(|(_, sf)| sf.setup == GAME_2)
+            .map(|(id, _)| *id)
+            .unwrap();
+        let resp = app.post(format!("/game/{}", id)).await.unwrap();
+        let session_id = resp[crate::SESSION_HEADER].last().to_string();
+
+        let mut listener = app.clone().bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.info()[0].connection().replace("http://", "");
+        async_std::task::spawn(async move { listener.accept().await });
+        let tcp = async_std::net::TcpStream::connect(&addr).await.unwrap();
+        let url = format!("ws://{}/sessions/{}/live", addr, session_id);
+        let (ws, _) = async_tungstenite::client_async(url, Connection::new(tcp))
+            .await
+            .unwrap();
+        let mut ws = WebSocketConnection::from(ws);
+
+        // neither a ping nor a binary frame ends the session
+        ws.send(Message::Ping(vec![1])).await.unwrap();
+        ws.send_bytes(vec![1, 2, 3]).await.unwrap();
+        ws.send_string("command: hint\nlimit: 1\n".to_string())
+            .await
+            .unwrap();
+        let reply = loop {
+            match ws.next().await {
+                Some(Ok(Message::Text(reply))) => break reply,
+                Some(Ok(_)) => continue,
+                other => panic!("no reply but {:?}", other),
+            }
+        };
+        let event: LiveEvent = serde_yaml::from_str(&reply).unwrap();
+        assert_eq!(
+            event,
+            LiveEvent::HintGranted {
+                coords: find_hints(&GAME_2, 1)
+            }
+        );
+        let session = app
+            .state()
+            .storage
+            .session(&Uuid::from_str(&session_id).unwrap());
+        assert_eq!(session.unwrap().hints_used, 1);
+
+        // an open game ends once the server is stopping
+        app.state().stopping.store(true, Ordering::SeqCst);
+        async_std::future::timeout(Duration::from_secs(5), async {
+            while let Some(Ok(message)) = ws.next().await {
+                assert!(!message.is_text(), "{:?}", message);
+            }
+        })
+        .await
+        .expect("the game did not end");
+    }
+
+    #[test]
+    fn test_commands_from_yaml() {
+        let command = serde_yaml::from_str::<LiveCommand>(
+            "command: mark\ncoord:\n  row: 1\n  col: 2\nstate: water\n",
+        )
+        .unwrap();
+        assert_eq!(command, mark(1, 2, CellMark::Water));
+        let command = serde_yaml::from_str::<LiveCommand>("command: hint\nlimit: 3\n").unwrap();
+        assert_eq!(command, LiveCommand::Hint { limit: 3 });
+        assert_eq!(parse_command("command: hint\nlimit: 3\n"), Ok(command));
+        let padded = format!("command: hint\nlimit: 3\n#{}", " ".repeat(MAX_BODY_BYTES));
+        assert!(parse_command(&padded).is_err());
+    }
+}
@@ -0,0 +1,573 @@
+use crate::setups::WireFormat;
+use crate::{State, CT_PLAIN, CT_YAML, DEPRECATION_HEADER};
+use bimaru_protocol::{Coords, Encoding};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+use tide::http::Method;
+use tide::{Endpoint, Request, Response, Server, StatusCode};
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+type ParamsFn = fn() -> Vec<Value>;
+
+fn schema_of<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+// Every field of a query struct becomes a query parameter.
+fn query_params_of<T: JsonSchema>() -> Vec<Value> {
+    let root = SchemaSettings::openapi3()
+        .with(|s| s.inline_subschemas = true)
+        .into_generator()
+        .into_root_schema_for::<T>();
+    let object = match root.schema.object {
+        Some(object) => object,
+        None => return vec![],
+    };
+    object
+        .properties
+        .iter()
+        .map(|(name, schema)| {
+            json!({
+                "name": name,
+                "in": "query",
+                "required": object.required.contains(name),
+                "schema": schema,
+            })
+        })
+        .collect()
+}
+
+pub struct Doc {
+    summary: &'static str,
+    query: Option<ParamsFn>,
+    headers: Vec<(&'static str, bool)>,
+    body: Vec<(String, SchemaFn)>,
+    responses: Vec<(u16, Option<(String, SchemaFn)>)>,
+    websocket: Option<(SchemaFn, SchemaFn)>,
+    deprecated: bool,
+}
+
+impl Doc {
+    pub fn new(summary: &'static str) -> Doc {
+        Doc {
+            summary,
+            query: None,
+            headers: vec![],
+            body: vec![],
+            responses: vec![],
+            websocket: None,
+            deprecated: false,
+        }
+    }
+
+    pub fn query<T: JsonSchema>(mut self) -> Doc {
+        self.query = Some(query_params_of::<T>);
+        self
+    }
+
+    pub fn header(mut self, name: &'static str, required: bool) -> Doc {
+        self.headers.push((name, required));
+        self
+    }
+
+    pub fn body<T: JsonSchema>(mut self, content_type: impl Into<String>) -> Doc {
+        self.body.push((content_type.into(), schema_of::<T>));
+        self
+    }
+
+    pub fn response<T: JsonSchema>(mut self, status: u16, content_type: impl Into<String>) -> Doc {
+        self.responses
+            .push((status, Some((content_type.into(), schema_of::<T>))));
+        self
+    }
+
+    // the entity in every wire format a setup can use
+    pub fn wire_body<T: JsonSchema>(mut self) -> Doc {
+        for format in WireFormat::ALL {
+            self = self.body::<T>(format.content_type(false));
+        }
+        self
+    }
+
+    pub fn wire_response<T: JsonSchema>(mut self, status: u16, nested: bool) -> Doc {
+        for format in WireFormat::ALL {
+            self = self.response::<T>(status, format.content_type(nested));
+        }
+        self
+    }
+
+    // cells in one encoding style, in every wire format
+    pub fn styled_body<E: Encoding>(mut self) -> Doc {
+        for format in WireFormat::ALL {
+            self = self.body::<Coords<E::Encoded>>(E::STYLE.content_type(format));
+        }
+        self
+    }
+
+    pub fn styled_response<E: Encoding>(mut self, status: u16) -> Doc {
+        for format in WireFormat::ALL {
+            self = self.response::<Coords<E::Encoded>>(status, E::STYLE.content_type(format));
+        }
+        self
+    }
+
+    pub fn text(self, status: u16) -> Doc {
+        self.response::<String>(status, CT_PLAIN)
+    }
+
+    pub fn empty(mut self, status: u16) -> Doc {
+        self.responses.push((status, None));
+        self
+    }
+
+    // messages exchanged after the upgrade, client to server and back
+    pub fn websocket<C: JsonSchema, S: JsonSchema>(mut self) -> Doc {
+        self.websocket = Some((schema_of::<C>, schema_of::<S>));
+        self.empty(101)
+    }
+
+    fn operation(&self, path: &str, gen: &mut SchemaGenerator) -> Value {
+        let mut operation = Map::new();
+        operation.insert("summary".to_string(), json!(self.summary));
+        if self.deprecated {
+            operation.insert("deprecated".to_string(), json!(true));
+        }
+
+        let mut parameters: Vec<Value> = path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": {"type": "string"},
+                })
+            })
+            .collect();
+        if let Some(query) = self.query {
+            parameters.extend(query());
+        }
+        for (name, required) in self.headers.iter() {
+            parameters.push(json!({
+                "name": name,
+                "in": "header",
+                "required": required,
+                "schema": {"type": "string"},
+            }));
+        }
+        if !parameters.is_empty() {
+            operation.insert("parameters".to_string(), Value::Array(parameters));
+        }
+
+        if !self.body.is_empty() {
+            let mut content = Map::new();
+            for (content_type, schema) in self.body.iter() {
+                content.insert(content_type.to_string(), json!({ "schema": schema(gen) }));
+            }
+            operation.insert(
+                "requestBody".to_string(),
+                json!({"required": true, "content": content}),
+            );
+        }
+
+        let mut responses = Map::new();
+        for (status, content) in self.responses.iter() {
+            let response = responses.entry(status.to_string()).or_insert_with(|| {
+                let reason = StatusCode::try_from(*status)
+                    .map(|s| s.canonical_reason())
+                    .unwrap_or("");
+                if self.deprecated {
+                    json!({
+                        "description": reason,
+                        "headers": {DEPRECATION_HEADER: {"schema": {"type": "string"}}},
+                    })
+                } else {
+                    json!({ "description": reason })
+                }
+            });
+            if let Some((content_type, schema)) = content {
+                response
+                    .as_object_mut()
+                    .unwrap()
+                    .entry("content")
+                    .or_insert_with(|| json!({}))
+                    .as_object_mut()
+                    .unwrap()
+                    .insert(content_type.to_string(), json!({ "schema": schema(gen) }));
+            }
+        }
+        operation.insert("responses".to_string(), Value::Object(responses));
+
+        if let Some((client, server)) = self.websocket {
+            operation.insert(
+                "x-websocket-messages".to_string(),
+                json!({"client": client(gen), "server": server(gen)}),
+            );
+        }
+        Value::Object(operation)
+    }
+}
+
+// Marks the responses of an old API version.
+struct Deprecated<E>(E);
+
+#[tide::utils::async_trait]
+impl<E: Endpoint<State>> Endpoint<State> for Deprecated<E> {
+    async fn call(&self, req: Request<State>) -> tide::Result {
+        let mut resp = self.0.call(req).await?;
+        resp.insert_header(DEPRECATION_HEADER, "true");
+        resp.insert_header("Link", "</v2>; rel=\"successor-version\"");
+        Ok(resp)
+    }
+}
+
+// Registers routes on the app and keeps their documentation next to them,
+// so the spec is always generated from the real route table.
+pub struct Routes<'a> {
+    app: &'a mut Server<State>,
+    prefix: &'static str,
+    deprecated: bool,
+    docs: Vec<(Method, String, Doc)>,
+}
+
+impl<'a> Routes<'a> {
+    pub fn new(app: &'a mut Server<State>) -> Routes<'a> {
+        Routes {
+            app,
+            prefix: "",
+            deprecated: false,
+            docs: vec![],
+        }
+    }
+
+    // Following routes are mounted below the prefix.
+    pub fn version(&mut self, prefix: &'static str, deprecated: bool) {
+        self.prefix = prefix;
+        self.deprecated = deprecated;
+    }
+
+    pub fn get(&mut self, path: &'static str, endpoint: impl Endpoint<State>, doc: Doc) {
+        self.route(Method::Get, path, endpoint, doc);
+    }
+
+    pub fn post(&mut self, path: &'static str, endpoint: impl Endpoint<State>, doc: Doc) {
+        self.route(Method::Post, path, endpoint, doc);
+    }
+
+    pub fn put(&mut self, path: &'static str, endpoint: impl Endpoint<State>, doc: Doc) {
+        self.route(Method::Put, path, endpoint, doc);
+    }
+
+    fn route(
+        &mut self,
+        method: Method,
+        path: &'static str,
+        endpoint: impl Endpoint<State>,
+        mut doc: Doc,
+    ) {
+        let path = format!("{}{}", self.prefix, path);
+        // limits::BodyLimits may refuse the body of any of these
+        if method == Method::Post || method == Method::Put {
+            doc = doc.text(413).text(415);
+        }
+        if self.deprecated {
+            self.app.at(&path).method(method, Deprecated(endpoint));
+        } else {
+            self.app.at(&path).method(method, endpoint);
+        }
+        doc.deprecated = self.deprecated;
+        self.docs.push((method, path, doc));
+    }
+
+    fn document(&self) -> Value {
+        let mut gen = SchemaSettings::openapi3().into_generator();
+        let mut paths = Map::new();
+        for (method, path, doc) in self.docs.iter() {
+            let template = path
+                .split('/')
+                .map(|segment| match segment.strip_prefix(':') {
+                    Some(name) => format!("{{{}}}", name),
+                    None => segment.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join("/");
+            let operation = doc.operation(path, &mut gen);
+            paths
+                .entry(template)
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+                .unwrap()
+                .insert(method.to_string().to_lowercase(), operation);
+        }
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "paths": paths,
+            "components": {"schemas": gen.definitions()},
+        })
+    }
+
+    // Adds the route serving the spec itself.
+    pub fn finish(mut self) {
+        self.docs.push((
+            Method::Get,
+            "/openapi.yaml".to_string(),
+            Doc::new("This document").response::<Value>(200, CT_YAML),
+        ));
+        let spec = serde_yaml::to_string(&self.document()).unwrap();
+        self.app.at("/openapi.yaml").get(move |_req| {
+            let spec = spec.clone();
+            async move {
+                Ok(Response::builder(200)
+                    .body(spec)
+                    .content_type(CT_YAML)
+                    .build())
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_app;
+    use crate::setups::GAME_2;
+    use serde_yaml::Value;
+    use tide_testing::TideTestingExt;
+
+    const SPEC_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.yaml");
+
+    async fn spec() -> String {
+        build_app()
+            .get("/openapi.yaml")
+            .recv_string()
+            .await
+            .unwrap()
+    }
+
+    #[async_std::test]
+    async fn test_spec_is_checked_in() {
+        let served = spec().await;
+        if std::env::var("UPDATE_OPENAPI").is_ok() {
+            std::fs::write(SPEC_FILE, &served).unwrap();
+        }
+        let checked_in = std::fs::read_to_string(SPEC_FILE).unwrap_or_default();
+        assert!(
+            served == checked_in,
+            "openapi.yaml is out of date, run the tests with UPDATE_OPENAPI=1"
+        );
+    }
+
+    // Every answer of the handlers has to be documented with its status and
+    // content type.
+    #[async_std::test]
+    async fn test_handlers_match_spec() {
+        let spec: Value = serde_yaml::from_str(&spec().await).unwrap();
+        let app = build_app();
+        let nested = "dd8fb490-72c8-485b-aeea-537b9be34e4b";
+        let list = "37073150-f43d-4609-94ec-dcbeffcb472a";
+        let unknown = "00000000-0000-0000-0000-000000000000";
+        let check =
+            serde_yaml::to_string(&crate::Check::from_cells(&crate::setups::cells(&GAME_2)))
+                .unwrap();
+        let requests: Vec<(&str, &str, String, Option<String>)> = vec![
+            (
+                "post",
+                "/game/{setup_id}",
+                format!("/game/{}", nested),
+                None,
+            ),
+            ("post", "/game/{setup_id}", format!("/game/{}", list), None),
+            (
+                "post",
+                "/game/{setup_id}",
+                format!("/game/{}", unknown),
+                None,
+            ),
+            (
+                "get",
+                "/game/{setup_id}/hint",
+                format!("/game/{}/hint?limit=2", nested),
+                None,
+            ),
+            (
+                "get",
+                "/game/{setup_id}/hint",
+                format!("/game/{}/hint", list),
+                None,
+            ),
+            (
+                "get",
+                "/game/{setup_id}/board",
+                format!("/game/{}/board", list),
+                None,
+            ),
+            (
+                "get",
+                "/game/{setup_id}/explanation",
+                format!("/game/{}/explanation?limit=2", list),
+                None,
+            ),
+            (
+                "get",
+                "/game/{setup_id}/explanation/boards",
+                format!("/game/{}/explanation/boards", list),
+                None,
+            ),
+            (
+                "post",
+                "/game/{setup_id}/explanation/next",
+                format!("/game/{}/explanation/next", list),
+                Some("ships: []".to_string()),
+            ),
+            (
+                "post",
+                "/game/{setup_id}/check",
+                format!("/game/{}/check", list),
+                Some(check),
+            ),
+            (
+                "post",
+                "/game/{setup_id}/check",
+                format!("/game/{}/check", list),
+                Some("-".to_string()),
+            ),
+            (
+                "post",
+                "/game/{setup_id}/validate",
+                format!("/game/{}/validate", list),
+                Some("ships: []".to_string()),
+            ),
+            (
+                "post",
+                "/game/{setup_id}/validate",
+                format!("/game/{}/validate", list),
+                Some("ships: [{row: 10, col: 0}]".to_string()),
+            ),
+            (
+                "post",
+                "/v1/game/{setup_id}",
+                format!("/v1/game/{}", nested),
+                None,
+            ),
+            (
+                "post",
+                "/v2/game/{setup_id}",
+                format!("/v2/game/{}", nested),
+                None,
+            ),
+            (
+                "post",
+                "/v2/game/{setup_id}",
+                format!("/v2/game/{}", unknown),
+                None,
+            ),
+            (
+                "get",
+                "/v2/game/{setup_id}/hint",
+                format!("/v2/game/{}/hint", nested),
+                None,
+            ),
+            (
+                "post",
+                "/v2/game/{setup_id}/validate",
+                format!("/v2/game/{}/validate", list),
+                Some("water: [{row: 0, col: 10}]".to_string()),
+            ),
+            (
+                "post",
+                "/v2/game/{setup_id}/check",
+                format!("/v2/game/{}/check", list),
+                Some("-".to_string()),
+            ),
+            (
+                "post",
+                "/v2/game/{setup_id}/check",
+                format!("/v2/game/{}/check", list),
+                Some("[".repeat(200)),
+            ),
+            ("get", "/leaderboard", "/leaderboard".to_string(), None),
+            (
+                "get",
+                "/leaderboard/{setup_id}",
+                format!("/leaderboard/{}", list),
+                None,
+            ),
+            (
+                "get",
+                "/players/{player_id}/stats",
+                "/players/nobody/stats".to_string(),
+                None,
+            ),
+            ("post", "/battles", "/battles".to_string(), None),
+            (
+                "get",
+                "/battles/{battle_id}",
+                format!("/battles/{}", unknown),
+                None,
+            ),
+            ("get", "/events", "/events".to_string(), None),
+            (
+                "get",
+                "/tournaments/{tournament_id}",
+                format!("/tournaments/{}", unknown),
+                None,
+            ),
+            (
+                "post",
+                "/tournaments",
+                "/tournaments".to_string(),
+                Some("-".to_string()),
+            ),
+            (
+                "post",
+                "/tournaments",
+                "/tournaments".to_string(),
+                Some(" ".repeat(crate::limits::MAX_BODY_BYTES + 1)),
+            ),
+            ("get", "/roster", "/roster".to_string(), None),
+            (
+                "get",
+                "/assignments/{student}",
+                "/assignments/nobody".to_string(),
+                None,
+            ),
+            ("get", "/daily", "/daily".to_string(), None),
+            (
+                "get",
+                "/daily/history",
+                "/daily/history?days=2".to_string(),
+                None,
+            ),
+            ("get", "/healthz", "/healthz".to_string(), None),
+            ("get", "/readyz", "/readyz".to_string(), None),
+            ("get", "/version", "/version".to_string(), None),
+            ("get", "/openapi.yaml", "/openapi.yaml".to_string(), None),
+        ];
+        for (method, template, url, body) in requests {
+            let resp = match (method, body) {
+                ("post", Some(body)) => app.post(&url).body_string(body).await,
+                ("post", None) => app.post(&url).await,
+                _ => app.get(&url).await,
+            }
+            .unwrap();
+            let status = resp.status().to_string();
+            let content_type = resp.content_type().unwrap().essence().to_string();
+            let documented = &spec["paths"][template][method]["responses"][status.as_str()]
+                ["content"][content_type.as_str()];
+            assert!(
+                documented.is_mapping(),
+                "{} {} answered {} {} which is not in the spec",
+                method,
+                url,
+                status,
+                content_type
+            );
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use crate::admin::{forbidden, is_admin};
+use crate::setups::{CreateFormat, EncodingStyle, SetupFormat, Setups};
+use crate::storage::{now_millis, Event, Session};
+use crate::{game_response, illegal_request, not_found, yaml_response, State, CT_YAML};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tide::Request;
+use uuid::{uuid, Uuid};
+
+// Assigned game ids are derived from the student name in this namespace.
+const ROSTER_NAMESPACE: Uuid = uuid!("6f1c2a4e-3b8d-4e0f-9a57-2d61c8b0e913");
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RosterUpload {
+    pub students: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Assignment {
+    pub student: String,
+    #[serde(with = "crate::uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub game_setup_id: Uuid,
+    #[serde(with = "crate::uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub board_id: Uuid,
+    pub create_format: CreateFormat,
+    pub encoding: EncodingStyle,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct AssignmentStatus {
+    #[serde(flatten)]
+    pub assignment: Assignment,
+    pub games_started: usize,
+    pub solved: bool,
+    pub hints_used: Option<u8>,
+}
+
+// The assignments of the last upload, folded from the events by the storage.
+#[derive(Clone, Debug, Default)]
+pub struct Roster {
+    assignments: Vec<Assignment>,
+}
+
+impl Roster {
+    // the board of the catalog played in the formats of the assignment
+    pub fn setup(&self, catalog: &Setups, id: &Uuid) -> Option<SetupFormat> {
+        let a = self.assignments.iter().find(|a| a.game_setup_id == *id)?;
+        catalog.get(&a.board_id).map(|sf| SetupFormat {
+            create_format: a.create_format,
+            encoding: a.encoding,
+            ..*sf
+        })
+    }
+
+    pub fn assignment(&self, student: &str) -> Option<&Assignment> {
+        self.assignments.iter().find(|a| a.student == student)
+    }
+}
+
+pub(crate) fn apply(roster: &mut Roster, event: &Event) {
+    if let Event::RosterUploaded { assignments, .. } = event {
+        roster.assignments = assignments.clone();
+    }
+}
+
+fn formats() -> Vec<(CreateFormat, EncodingStyle)> {
+    let mut result = vec![];
+    for encoding in EncodingStyle::ALL {
+        for create_format in [CreateFormat::List, CreateFormat::Nested] {
+            result.push((create_format, encoding));
+        }
+    }
+    result
+}
+
+// The board and the formats are picked by the bytes of the game id, which
+// is derived from the student name. Students take their boards in the order
+// of their game ids and a taken board passes to the next free one, so no two
+// students share a board until the catalog runs out, and the upload order
+// does not matter.
+pub fn assign(catalog: &Setups, students: &[String]) -> Roster {
+    let mut boards: Vec<Uuid> = catalog.keys().copied().collect();
+    boards.sort();
+    let formats = formats();
+    let mut roster = Roster::default();
+    if boards.is_empty() {
+        return roster;
+    }
+    let mut ids: Vec<(Uuid, &String)> = vec![];
+    for student in students {
+        if ids.iter().all(|(_, s)| s != &student) {
+            ids.push((Uuid::new_v5(&ROSTER_NAMESPACE, student.as_bytes()), student));
+        }
+    }
+    let mut order: Vec<usize> = (0..ids.len()).collect();
+    order.sort_by_key(|k| ids[*k].0);
+    let mut taken = vec![false; boards.len()];
+    let mut board_of = vec![0; ids.len()];
+    for k in order {
+        if taken.iter().all(|t| *t) {
+            taken.fill(false);
+        }
+        let (high, _) = ids[k].0.as_u64_pair();
+        let mut i = (high % boards.len() as u64) as usize;
+        while taken[i] {
+            i = (i + 1) % boards.len();
+        }
+        taken[i] = true;
+        board_of[k] = i;
+    }
+    for ((game_setup_id, student), i) in ids.into_iter().zip(board_of) {
+        let (_, low) = game_setup_id.as_u64_pair();
+        let (create_format, encoding) = formats[(low % formats.len() as u64) as usize];
+        roster.assignments.push(Assignment {
+            student: student.clone(),
+            game_setup_id,
+            board_id: boards[i],
+            create_format,
+            encoding,
+        });
+    }
+    roster
+}
+
+pub fn export(roster: &Roster, sessions: &[Session]) -> Vec<AssignmentStatus> {
+    roster
+        .assignments
+        .iter()
+        .map(|a| {
+            let own: Vec<&Session> = sessions
+                .iter()
+                .filter(|s| s.setup_id == a.game_setup_id)
+                .collect();
+            let solved: Vec<&&Session> = own.iter().filter(|s| s.completed_at.is_some()).collect();
+            AssignmentStatus {
+                assignment: a.clone(),
+                games_started: own.len(),
+                solved: !solved.is_empty(),
+                hints_used: solved.iter().map(|s| s.hints_used).min(),
+            }
+        })
+        .collect()
+}
+
+pub async fn upload(mut req: Request<State>) -> tide::Result {
+    if !is_admin(&req) {
+        return forbidden();
+    }
+    let body_str = req.body_string().await?;
+    if let Ok(entity) = serde_yaml::from_str::<RosterUpload>(&body_str) {
+        let roster = assign(&req.state().setups, &entity.students);
+        req.state().storage.record(Event::RosterUploaded {
+            assignments: roster.assignments.clone(),
+            at: now_millis(),
+        })?;
+        yaml_response(200, CT_YAML, &roster.assignments)
+    } else {
+        illegal_request("Could not parse entity")
+    }
+}
+
+pub async fn show(req: Request<State>) -> tide::Result {
+    if !is_admin(&req) {
+        return forbidden();
+    }
+    let sessions = req.state().storage.sessions();
+    let roster = req.state().storage.roster();
+    yaml_response(200, CT_YAML, &export(&roster, &sessions))
+}
+
+pub async fn assignment(req: Request<State>) -> tide::Result {
+    let student: &str = req.param("student")?;
+    let assigned = req.state().storage.roster().assignment(student).cloned();
+    if let Some(a) = assigned {
+        let sf = req.state().setup(&a.game_setup_id).unwrap();
+        game_response(200, a.game_setup_id, &sf)
+    } else {
+        not_found("Unknown student")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_app_from;
+    use crate::setups::build_all;
+    use crate::storage::{temp_path, FileStorage, MemoryStorage};
+    use bimaru_protocol::NewGame;
+    use std::sync::Arc;
+    use tide_testing::TideTestingExt;
+
+    fn students(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_assign() {
+        let catalog = build_all();
+        let roster = assign(&catalog, &students(&["ann", "ben", "cy", "ann"]));
+        assert_eq!(roster.assignments.len(), 3);
+        let again = assign(&catalog, &students(&["ann", "ben", "cy"]));
+        assert_eq!(roster.assignments, again.assignments);
+        // the upload order does not change the assignments
+        let reordered = assign(&catalog, &students(&["cy", "ann", "ben"]));
+        for student in ["ann", "ben", "cy"] {
+            assert_eq!(reordered.assignment(student), roster.assignment(student));
+        }
+
+        let ann = roster.assignment("ann").unwrap();
+        let ben = roster.assignment("ben").unwrap();
+        assert_ne!(ann.board_id, ben.board_id);
+        assert_ne!(ann.game_setup_id, ben.game_setup_id);
+        assert_ne!(
+            (ann.create_format, ann.encoding),
+            (ben.create_format, ben.encoding)
+        );
+        let sf = roster.setup(&catalog, &ann.game_setup_id).unwrap();
+        assert_eq!(sf.setup, catalog.get(&ann.board_id).unwrap().setup);
+        assert_eq!(sf.encoding, ann.encoding);
+        // a roster as large as the catalog plays every board once
+        let names: Vec<String> = (0..catalog.len())
+            .map(|i| format!("student {}", i))
+            .collect();
+        let full = assign(&catalog, &names);
+        let mut boards: Vec<Uuid> = full.assignments.iter().map(|a| a.board_id).collect();
+        boards.sort();
+        boards.dedup();
+        assert_eq!(boards.len(), catalog.len());
+        assert_eq!(assign(&catalog, &names).assignments, full.assignments);
+        assert!(assign(&Setups::new(), &students(&["ann"]))
+            .assignments
+            .is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_roster() {
+        let token = Some("secret".to_string());
+        let app = build_app_from(build_all(), Arc::new(MemoryStorage::new()), token);
+        let resp = app
+            .put("/roster")
+            .body_string("students: [ann, ben]".to_string())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 403);
+        let assignments: Vec<Assignment> = serde_yaml::from_str(
+            &app.put("/roster")
+                .header("Authorization", "Bearer secret")
+                .body_string("students: [ann, ben]".to_string())
+                .recv_string()
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(assignments.len(), 2);
+
+        let ann = &assignments[0];
+        let resp = app.get("/assignments/ann").await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let resp = app.get("/assignments/nobody").await.unwrap();
+        assert_eq!(resp.status(), 404);
+
+        // the assigned id plays like any setup
+        let setup = app.state().setup(&ann.game_setup_id).unwrap().setup;
+        let resp = app
+            .post(format!("/game/{}?player=ann", ann.game_setup_id))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        let session_id = resp[crate::SESSION_HEADER].last().to_string();
+        let solution =
+            serde_yaml::to_string(&crate::Check::from_cells(&crate::setups::cells(&setup)))
+                .unwrap();
+        let answer = app
+            .post(format!("/game/{}/check", ann.game_setup_id))
+            .header(crate::SESSION_HEADER, session_id)
+            .body_string(solution)
+            .recv_string()
+            .await
+            .unwrap();
+        assert_eq!(answer, "Well done!");
+
+        let export: Vec<AssignmentStatus> = serde_yaml::from_str(
+            &app.get("/roster")
+                .header("Authorization", "Bearer secret")
+                .recv_string()
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(export.len(), 2);
+        assert!(export[0].solved);
+        assert_eq!(export[0].games_started, 1);
+        assert!(!export[1].solved);
+    }
+
+    #[async_std::test]
+    async fn test_roster_survives_restart() {
+        let path = temp_path("roster");
+        let token = Some("secret".to_string());
+        let storage = Arc::new(FileStorage::open(&path).unwrap());
+        let app = build_app_from(build_all(), storage, token.clone());
+        let resp = app
+            .put("/roster")
+            .header("Authorization", "Bearer secret")
+            .body_string("students: [ann]".to_string())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        drop(app);
+
+        let storage = Arc::new(FileStorage::open(&path).unwrap());
+        let app = build_app_from(build_all(), storage, token);
+        let ann: NewGame =
+            serde_yaml::from_str(&app.get("/assignments/ann").recv_string().await.unwrap())
+                .unwrap();
+        assert_eq!(
+            ann.game_setup_id,
+            Uuid::new_v5(&ROSTER_NAMESPACE, "ann".as_bytes())
+        );
+        let resp = app
+            .post(format!("/game/{}", ann.game_setup_id))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        std::fs::remove_file(path).unwrap();
+    }
+}
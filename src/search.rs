@@ -0,0 +1,363 @@
+use crate::bitboard::{bit, cells_of as cells_of_mask, halo, of_cells, ALL, COL_0, ROW_0};
+use crate::setups::{cells as cells_of_setup, OccupiedCells, Setup, FLEET, MAX_INDEX, MIN_INDEX};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Known {
+    Ship,
+    Water,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Puzzle {
+    pub rows: [u8; 10],
+    pub cols: [u8; 10],
+    pub known: [[Option<Known>; 10]; 10],
+}
+
+impl Puzzle {
+    pub fn new(rows: [u8; 10], cols: [u8; 10]) -> Puzzle {
+        Puzzle {
+            rows,
+            cols,
+            known: [[None; 10]; 10],
+        }
+    }
+
+    pub fn from_setup(setup: &Setup) -> Puzzle {
+        Puzzle::new(setup.occupied_rows(), setup.occupied_cols())
+    }
+
+    pub fn reveal(&mut self, row: usize, col: usize, known: Known) {
+        self.known[row][col] = Some(known);
+    }
+}
+
+// A ship at one position and orientation, with the cells around it that no
+// other ship may use.
+struct Placement {
+    position: usize,
+    ship: u128,
+    around: u128,
+    cells: Vec<(usize, usize)>,
+}
+
+// Placements of each ship size by position, rows before columns, the order
+// the search visits them in.
+fn placements(size: usize) -> Vec<Placement> {
+    let orientations: &[bool] = if size == 1 { &[false] } else { &[false, true] };
+    let mut result = vec![];
+    for position in 0..100 {
+        for &vertical in orientations {
+            if let Some(cells) = cells_of(position / 10, position % 10, size, vertical) {
+                let ship = of_cells(&cells).unwrap();
+                result.push(Placement {
+                    position,
+                    ship,
+                    around: halo(ship) & !ship,
+                    cells,
+                });
+            }
+        }
+    }
+    result
+}
+
+// Depth-first placement of the fleet, largest ships first. Ships of equal
+// size are placed in increasing position order so every fleet is visited once.
+// Cells are bitboard masks so each candidate costs a few word operations.
+struct Search<'a> {
+    placements: &'a [Vec<Placement>; 5],
+    ships: u128,
+    water: u128,
+    rows: [u8; 10],
+    cols: [u8; 10],
+    grid: Setup,
+    occupied: u128,
+    blocked: u128,
+    placed_rows: [u8; 10],
+    placed_cols: [u8; 10],
+    limit: usize,
+    found: Vec<Setup>,
+}
+
+fn cells_of(row: usize, col: usize, size: usize, vertical: bool) -> Option<Vec<(usize, usize)>> {
+    let (end_row, end_col) = if vertical {
+        (row + size - 1, col)
+    } else {
+        (row, col + size - 1)
+    };
+    if end_row > MAX_INDEX || end_col > MAX_INDEX {
+        return None;
+    }
+    Some(
+        (0..size)
+            .map(|k| {
+                if vertical {
+                    (row + k, col)
+                } else {
+                    (row, col + k)
+                }
+            })
+            .collect(),
+    )
+}
+
+fn mask_of(puzzle: &Puzzle, known: Known) -> u128 {
+    (MIN_INDEX..=MAX_INDEX)
+        .flat_map(|r| (MIN_INDEX..=MAX_INDEX).map(move |c| (r, c)))
+        .filter(|&(r, c)| puzzle.known[r][c] == Some(known))
+        .fold(0, |mask, (r, c)| mask | bit(r, c))
+}
+
+impl<'a> Search<'a> {
+    fn new(puzzle: &Puzzle, placements: &'a [Vec<Placement>; 5], limit: usize) -> Search<'a> {
+        Search {
+            placements,
+            ships: mask_of(puzzle, Known::Ship),
+            water: mask_of(puzzle, Known::Water),
+            rows: puzzle.rows,
+            cols: puzzle.cols,
+            grid: [[0; 10]; 10],
+            occupied: 0,
+            blocked: 0,
+            placed_rows: [0; 10],
+            placed_cols: [0; 10],
+            limit,
+            found: vec![],
+        }
+    }
+
+    fn fits(&self, placement: &Placement) -> bool {
+        // a revealed ship cell next to this ship can never be covered
+        if placement.ship & (self.blocked | self.water) != 0 || placement.around & self.ships != 0 {
+            return false;
+        }
+        let mut rows = self.placed_rows;
+        let mut cols = self.placed_cols;
+        for &(r, c) in placement.cells.iter() {
+            rows[r] += 1;
+            cols[c] += 1;
+            if rows[r] > self.rows[r] || cols[c] > self.cols[c] {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn set(&mut self, placement: &Placement, first: u8, on: bool) {
+        for (k, &(r, c)) in placement.cells.iter().enumerate() {
+            if on {
+                self.grid[r][c] = first + k as u8;
+                self.placed_rows[r] += 1;
+                self.placed_cols[c] += 1;
+            } else {
+                self.grid[r][c] = 0;
+                self.placed_rows[r] -= 1;
+                self.placed_cols[c] -= 1;
+            }
+        }
+        self.occupied ^= placement.ship;
+        self.blocked = halo(self.occupied);
+    }
+
+    // every unfinished row and column still has enough free cells
+    fn feasible(&self) -> bool {
+        if self.ships & self.blocked & !self.occupied != 0 {
+            return false;
+        }
+        let free = ALL & !self.blocked & !self.water;
+        (MIN_INDEX..=MAX_INDEX).all(|i| {
+            self.rows[i] - self.placed_rows[i] <= (free & ROW_0 << (i * 10)).count_ones() as u8
+                && self.cols[i] - self.placed_cols[i] <= (free & COL_0 << i).count_ones() as u8
+        })
+    }
+
+    fn complete(&self) -> bool {
+        self.placed_rows == self.rows
+            && self.placed_cols == self.cols
+            && self.ships & !self.occupied == 0
+    }
+
+    fn place(&mut self, ship: usize, first: u8, min_position: usize) {
+        if self.found.len() >= self.limit {
+            return;
+        }
+        if ship == FLEET.len() {
+            if self.complete() {
+                self.found.push(self.grid);
+            }
+            return;
+        }
+        let size = FLEET[ship];
+        let placements = self.placements;
+        for placement in placements[size]
+            .iter()
+            .filter(|p| p.position >= min_position)
+        {
+            if !self.fits(placement) {
+                continue;
+            }
+            self.set(placement, first, true);
+            if self.feasible() {
+                let next = ship + 1;
+                let next_min = if next < FLEET.len() && FLEET[next] == size {
+                    placement.position + 1
+                } else {
+                    0
+                };
+                self.place(next, first + size as u8, next_min);
+            }
+            self.set(placement, first, false);
+            if self.found.len() >= self.limit {
+                break;
+            }
+        }
+    }
+}
+
+// revealed ship cells touching at a corner can never be part of a fleet
+fn corners_touch(puzzle: &Puzzle) -> bool {
+    let ship = |r: usize, c: usize| puzzle.known[r][c] == Some(Known::Ship);
+    (MIN_INDEX..MAX_INDEX).any(|r| {
+        (MIN_INDEX..MAX_INDEX)
+            .any(|c| (ship(r, c) && ship(r + 1, c + 1)) || (ship(r, c + 1) && ship(r + 1, c)))
+    })
+}
+
+fn search(puzzle: &Puzzle, limit: usize) -> Vec<Setup> {
+    if corners_touch(puzzle) {
+        return vec![];
+    }
+    let placements = [
+        vec![],
+        placements(1),
+        placements(2),
+        placements(3),
+        placements(4),
+    ];
+    let mut search = Search::new(puzzle, &placements, limit);
+    search.place(0, 1, 0);
+    search.found
+}
+
+pub fn solve(puzzle: &Puzzle) -> Option<Setup> {
+    search(puzzle, 1).pop()
+}
+
+pub fn count_solutions(puzzle: &Puzzle, limit: usize) -> usize {
+    search(puzzle, limit).len()
+}
+
+fn with_revealed(setup: &Setup, cells: &[(usize, usize)]) -> Puzzle {
+    let mut puzzle = Puzzle::from_setup(setup);
+    for &(r, c) in cells {
+        let known = if setup[r][c] > 0 {
+            Known::Ship
+        } else {
+            Known::Water
+        };
+        puzzle.reveal(r, c, known);
+    }
+    puzzle
+}
+
+// Reveals a ship cell another solution leaves empty until the setup is the
+// only solution left, so the hint paths can serve every cell. Then every cell
+// the uniqueness does not depend on is dropped again. The set is irreducible,
+// no revealed cell can be spared, but a smaller set may still exist.
+pub fn irreducible_hints(setup: &Setup) -> Vec<(usize, usize)> {
+    let occupied = |s: &Setup| of_cells(&cells_of_setup(s)).unwrap();
+    let mut cells: Vec<(usize, usize)> = vec![];
+    loop {
+        let other = search(&with_revealed(setup, &cells), 2)
+            .into_iter()
+            .map(|s| occupied(&s))
+            .find(|&other| other != occupied(setup));
+        let other = match other {
+            Some(other) => other,
+            None => break,
+        };
+        // both place 20 ship cells, so the setup has one the other lacks
+        cells.push(cells_of_mask(occupied(setup) & !other)[0]);
+    }
+    let mut k = 0;
+    while k < cells.len() {
+        let mut fewer = cells.clone();
+        fewer.remove(k);
+        if count_solutions(&with_revealed(setup, &fewer), 2) == 1 {
+            cells = fewer;
+        } else {
+            k += 1;
+        }
+    }
+    cells.sort();
+    cells
+}
+
+// Only ship cells, the setup is the one solution they leave and each of them
+// is needed for that.
+pub fn is_irreducible(setup: &Setup, cells: &[(usize, usize)]) -> bool {
+    cells.iter().all(|&(r, c)| setup[r][c] > 0)
+        && count_solutions(&with_revealed(setup, cells), 2) == 1
+        && (0..cells.len()).all(|k| {
+            let mut fewer = cells.to_vec();
+            fewer.remove(k);
+            count_solutions(&with_revealed(setup, &fewer), 2) == 2
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setups::{build_all, cells, Reveals, GAME_1, GAME_2};
+
+    #[test]
+    fn test_solve_with_all_ships_revealed() {
+        let mut puzzle = Puzzle::from_setup(&GAME_1);
+        for (r, c) in cells(&GAME_1) {
+            puzzle.reveal(r, c, Known::Ship);
+        }
+        let solution = solve(&puzzle).unwrap();
+        assert_eq!(cells(&solution), cells(&GAME_1));
+        assert_eq!(count_solutions(&puzzle, 2), 1);
+    }
+
+    #[test]
+    fn test_solve_from_counts() {
+        let puzzle = Puzzle::from_setup(&GAME_2);
+        let solution = solve(&puzzle).unwrap();
+        assert_eq!(Puzzle::from_setup(&solution), puzzle);
+        assert!(crate::setups::is_valid(&solution));
+    }
+
+    #[test]
+    fn test_irreducible_hints() {
+        let hints = irreducible_hints(&GAME_2);
+        // the catalog stores the same cells
+        assert!(build_all()
+            .values()
+            .any(|sf| sf.setup == GAME_2 && sf.irreducible_hints == Some(Reveals::of(&hints))));
+        assert!(is_irreducible(&GAME_2, &hints));
+    }
+
+    #[test]
+    fn test_catalog_hints_are_irreducible() {
+        for (id, sf) in build_all().iter() {
+            let hints = sf.irreducible_hints.unwrap().cells();
+            assert!(is_irreducible(&sf.setup, &hints), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_unsolvable() {
+        let mut puzzle = Puzzle::from_setup(&GAME_2);
+        puzzle.reveal(0, 0, Known::Ship);
+        puzzle.reveal(0, 1, Known::Ship);
+        puzzle.reveal(1, 1, Known::Ship);
+        assert_eq!(solve(&puzzle), None);
+
+        let mut puzzle = Puzzle::from_setup(&GAME_2);
+        puzzle.rows = [0; 10];
+        assert_eq!(solve(&puzzle), None);
+    }
+}
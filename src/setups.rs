@@ -1,21 +1,52 @@
+pub use bimaru_protocol::{Coord, EncodingStyle, WireFormat};
 use im::{hashmap, HashMap};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
 use uuid::{uuid, Uuid};
 
 pub type Setup = [[u8; 10]; 10];
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct SetupFormat{
     pub create_format: CreateFormat,
-    pub hint_format: HintFormat,
-    pub setup: Setup
+    pub encoding: EncodingStyle,
+    pub wire_format: WireFormat,
+    pub setup: Setup,
+    pub irreducible_hints: Option<Reveals>
 }
 pub type Setups = HashMap<Uuid, SetupFormat>;
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub enum HintFormat {
-    List,
-    Nested,
+// Revealed ship cells as bits row * 10 + col.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Reveals(pub u128);
+
+impl Reveals {
+    pub fn of(cells: &[(usize, usize)]) -> Reveals {
+        Reveals(cells.iter().fold(0, |mask, (r, c)| mask | 1 << (r * 10 + c)))
+    }
+
+    pub fn cells(&self) -> Vec<(usize, usize)> {
+        (0..100).filter(|i| self.0 & 1 << i > 0).map(|i| (i / 10, i % 10)).collect()
+    }
+
+    pub fn len(&self) -> u8 {
+        self.0.count_ones() as u8
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
 }
-#[derive(Clone, Copy, PartialEq, Debug)]
+
+impl From<Reveals> for Vec<Coord> {
+    fn from(reveals: Reveals) -> Vec<Coord> {
+        reveals.cells().into_iter().map(|(row, col)| Coord { row, col }).collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
 pub enum CreateFormat {
     List,
     Nested
@@ -24,68 +55,166 @@ pub enum CreateFormat {
 pub fn build_all() -> Setups {
     hashmap! {
         uuid!("5109c2b1-7c4d-4f56-9be2-f6675c968331") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::Nested, setup: GAME_0},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: GAME_0, irreducible_hints: Some(Reveals(0x20000000800001200000008))},
         uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: GAME_1},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: GAME_1, irreducible_hints: Some(Reveals(0x2010900000420))},
         uuid!("37073150-f43d-4609-94ec-dcbeffcb472a") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: GAME_2},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: GAME_2, irreducible_hints: Some(Reveals(0xc0000000000401100))},
         uuid!("3a7a8f44-b224-40ff-9c5c-58a1b60eab4b") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: GAME_3},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: GAME_3, irreducible_hints: Some(Reveals(0x2000300140000))},
         uuid!("81520eec-47d6-43da-a692-2926a3dc2871") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: GAME_4},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: GAME_4, irreducible_hints: Some(Reveals(0x200008080))},
         uuid!("63dac12b-2afa-49e0-b133-edce3955b49a") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: GAME_5},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: GAME_5, irreducible_hints: Some(Reveals(0x2000000000080a00201))},
         uuid!("399165ec-72cc-43f2-ba41-6f19f89afcf3") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_6()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_6(), irreducible_hints: Some(Reveals(0x20000024000000800012))},
         uuid!("d8ad5555-cd70-4b51-9d73-93272950178d") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_7()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_7(), irreducible_hints: Some(Reveals(0x4080000ac000))},
         uuid!("31f1c720-e0e7-47e7-be5c-a94d32e1088d") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_8()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_8(), irreducible_hints: Some(Reveals(0x100000000000000800024))},
         uuid!("37a5acdf-6d9b-4de1-b4f1-6647fbb6feb0") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_9()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_9(), irreducible_hints: Some(Reveals(0x8000030200020))},
         uuid!("a087ab92-a5bd-4e1f-b61d-da27c355279e") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_10()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_10(), irreducible_hints: Some(Reveals(0x210004f001))},
         uuid!("a470fa68-cc2d-4295-80b7-411869b65ddb") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_11()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_11(), irreducible_hints: Some(Reveals(0x6000008002080))},
         uuid!("685023f9-ebcb-499c-96c8-5a88bfbfb14d") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_12()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_12(), irreducible_hints: Some(Reveals(0x2040000011a))},
         uuid!("bb63cffd-b5cc-4803-a03f-40922646d0d4") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_13()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_13(), irreducible_hints: Some(Reveals(0x400000111001c50000))},
         uuid!("0de28b51-e8ef-41d5-a1e6-131b51c4a638") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_14()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_14(), irreducible_hints: Some(Reveals(0x10000010040))},
         uuid!("06d6bab1-ff17-4c9e-8861-e6ae4b227b86") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_15()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_15(), irreducible_hints: Some(Reveals(0x4004850800))},
         uuid!("2c89e73e-c9c8-48da-a11c-55e61145ab19") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_16()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_16(), irreducible_hints: Some(Reveals(0x400180000004000120))},
         uuid!("4c182baf-e244-431f-9582-5eed5345d89f") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_17()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_17(), irreducible_hints: Some(Reveals(0x8004000d400))},
         uuid!("1c5abf4c-1b88-4446-9ac2-30c43cacba2a") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_18()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_18(), irreducible_hints: Some(Reveals(0x200000000001104400080))},
         uuid!("9cfda689-fee4-4a87-b82b-49dd379f3cad") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_19()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_19(), irreducible_hints: Some(Reveals(0x400000010300010))},
         uuid!("b2f24476-c8ae-47bb-9d8d-432de66c5cfa") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_20()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_20(), irreducible_hints: Some(Reveals(0x210003ca00))},
         uuid!("2eebb87b-b86a-4b36-8c19-12a145131d02") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_21()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_21(), irreducible_hints: Some(Reveals(0x2012400080010))},
         uuid!("90bdd6f1-5302-4ba0-87d7-0f84b9657bc7") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_22()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_22(), irreducible_hints: Some(Reveals(0xc000000008020002))},
         uuid!("726f27ee-8d35-4e1b-8e60-52a3c283a0e4") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_23()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_23(), irreducible_hints: Some(Reveals(0x13020000800))},
         uuid!("b365cb28-3578-4b70-a0b5-4b2983ead286") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_24()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_24(), irreducible_hints: Some(Reveals(0x500024004))},
         uuid!("3fcc9068-e55e-4054-a080-2d5994a40f62") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_25()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_25(), irreducible_hints: Some(Reveals(0x10000000004014000201))},
         uuid!("e998c938-8a22-4084-94b2-02b01bcc8c12") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_26()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_26(), irreducible_hints: Some(Reveals(0x2000000000000000030))},
         uuid!("d12d3fdf-b8c1-47e2-8c13-3a1415bea7b8") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_27()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_27(), irreducible_hints: Some(Reveals(0x800000001004080400))},
         uuid!("d3993d51-041d-4c86-aa69-a6eb85c41e11") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_28()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_28(), irreducible_hints: Some(Reveals(0x2000000000000040440))},
         uuid!("4a3df0e3-9e6d-4bae-a2f4-02f23bad933f") =>
-            SetupFormat{create_format: CreateFormat::List,   hint_format: HintFormat::Nested, setup: game_29()},
+            SetupFormat{create_format: CreateFormat::List,   encoding: EncodingStyle::ConsList, wire_format: WireFormat::Yaml, setup: game_29(), irreducible_hints: Some(Reveals(0x80000900309))},
         uuid!("2320ab5f-72ff-4dcf-976a-f085401c70e0") =>
-            SetupFormat{create_format: CreateFormat::Nested, hint_format: HintFormat::List,   setup: game_30()},
+            SetupFormat{create_format: CreateFormat::Nested, encoding: EncodingStyle::FlatList, wire_format: WireFormat::Yaml, setup: game_30(), irreducible_hints: Some(Reveals(0x100000000000060000d8000))},
+        }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    #[serde(with = "crate::uuid_as_string")]
+    pub id: Uuid,
+    pub create_format: CreateFormat,
+    #[serde(alias = "hint_format")]
+    pub encoding: EncodingStyle,
+    #[serde(default)]
+    pub wire_format: WireFormat,
+    pub setup: Setup,
+    #[serde(default, alias = "minimal_hints", skip_serializing_if = "Option::is_none")]
+    pub irreducible_hints: Option<Vec<Coord>>,
+}
+
+pub fn to_catalog(setups: &Setups) -> Vec<CatalogEntry> {
+    let mut result: Vec<CatalogEntry> = setups
+        .iter()
+        .map(|(id, sf)| CatalogEntry {
+            id: *id,
+            create_format: sf.create_format,
+            encoding: sf.encoding,
+            wire_format: sf.wire_format,
+            setup: sf.setup,
+            irreducible_hints: sf.irreducible_hints.map(Vec::from),
+        })
+        .collect();
+    result.sort_by_key(|e| e.id);
+    result
+}
+
+pub fn from_catalog(entries: Vec<CatalogEntry>) -> io::Result<Setups> {
+    let mut result = Setups::new();
+    for e in entries {
+        if !is_valid(&e.setup) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Setup {} is not valid", e.id),
+            ));
+        }
+        if !has_fleet(&e.setup) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Setup {} does not hold the fleet", e.id),
+            ));
+        }
+        let mut irreducible_hints = None;
+        if let Some(coords) = e.irreducible_hints {
+            if let Some(c) = coords
+                .iter()
+                .find(|c| c.row > MAX_INDEX || c.col > MAX_INDEX)
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Setup {} has hint {},{} off the board", e.id, c.row, c.col),
+                ));
+            }
+            let cells: Vec<(usize, usize)> = coords.iter().map(|c| (c.row, c.col)).collect();
+            let hints = Reveals::of(&cells);
+            // no hint route serves more than MAX_HINTS
+            if hints.len() > crate::MAX_HINTS {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Setup {} has more than {} irreducible hints",
+                        e.id,
+                        crate::MAX_HINTS
+                    ),
+                ));
+            }
+            if !crate::search::is_irreducible(&e.setup, &hints.cells()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Setup {} has irreducible hints that are not", e.id),
+                ));
+            }
+            irreducible_hints = Some(hints);
         }
+        result.insert(
+            e.id,
+            SetupFormat {
+                create_format: e.create_format,
+                encoding: e.encoding,
+                wire_format: e.wire_format,
+                setup: e.setup,
+                irreducible_hints,
+            },
+        );
+    }
+    Ok(result)
+}
+
+pub fn load_catalog<P: AsRef<Path>>(path: P) -> io::Result<Setups> {
+    let content = std::fs::read_to_string(path)?;
+    let entries = serde_yaml::from_str::<Vec<CatalogEntry>>(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    from_catalog(entries)
 }
 
 pub const MAX_INDEX: usize = 9;
@@ -301,6 +430,110 @@ impl Searchable for Setup {
     }
 }
 
+pub fn is_valid(setup: &Setup) -> bool {
+    let mut values: Vec<u8> = setup.iter().flatten().filter(|e| **e > 0).copied().collect();
+    values.sort_unstable();
+    if values != (1..=20).collect::<Vec<u8>>() {
+        return false;
+    }
+    // corners do not touch
+    for i in MIN_INDEX..MAX_INDEX {
+        for j in MIN_INDEX..MAX_INDEX {
+            if setup[i][j] > 0 && setup[i + 1][j + 1] > 0 {
+                return false;
+            }
+            if setup[i][j + 1] > 0 && setup[i + 1][j] > 0 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// ship sizes in the order their cells are numbered: 1..=4, 5..=7, 8..=10, ...
+pub const FLEET: [usize; 10] = [4, 3, 3, 2, 2, 2, 1, 1, 1, 1];
+
+pub fn ship_of(value: u8) -> Option<usize> {
+    let mut last: usize = 0;
+    for (ship, size) in FLEET.iter().enumerate() {
+        last += size;
+        if value > 0 && value as usize <= last {
+            return Some(ship);
+        }
+    }
+    None
+}
+
+pub fn cells(setup: &Setup) -> Vec<(usize, usize)> {
+    let mut result = vec![];
+    for (i, row) in setup.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            if *value > 0 {
+                result.push((i, j));
+            }
+        }
+    }
+    result
+}
+
+// the occupied cells form exactly the ships of FLEET
+pub fn has_fleet(setup: &Setup) -> bool {
+    from_cells(&cells(setup)).is_some()
+}
+
+pub fn from_cells(cells: &[(usize, usize)]) -> Option<Setup> {
+    let mut occupied = [[false; 10]; 10];
+    for &(i, j) in cells {
+        if i > MAX_INDEX || j > MAX_INDEX || occupied[i][j] {
+            return None;
+        }
+        occupied[i][j] = true;
+    }
+    let mut ships: Vec<Vec<(usize, usize)>> = vec![];
+    let mut seen = [[false; 10]; 10];
+    for i in MIN_INDEX..=MAX_INDEX {
+        for j in MIN_INDEX..=MAX_INDEX {
+            if occupied[i][j] && !seen[i][j] {
+                let mut ship = vec![];
+                let mut stack = vec![(i, j)];
+                seen[i][j] = true;
+                while let Some((r, c)) = stack.pop() {
+                    ship.push((r, c));
+                    let neighbours = [
+                        (r.wrapping_sub(1), c),
+                        (r + 1, c),
+                        (r, c.wrapping_sub(1)),
+                        (r, c + 1),
+                    ];
+                    for (nr, nc) in neighbours {
+                        if nr <= MAX_INDEX && nc <= MAX_INDEX && occupied[nr][nc] && !seen[nr][nc] {
+                            seen[nr][nc] = true;
+                            stack.push((nr, nc));
+                        }
+                    }
+                }
+                ship.sort_unstable();
+                ships.push(ship);
+            }
+        }
+    }
+    ships.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+    if ships.iter().map(|s| s.len()).collect::<Vec<usize>>() != FLEET {
+        return None;
+    }
+    let mut result: Setup = [[0u8; 10]; 10];
+    let mut value: u8 = 0;
+    for (i, j) in ships.into_iter().flatten() {
+        value += 1;
+        result[i][j] = value;
+    }
+    if is_valid(&result) {
+        Some(result)
+    } else {
+        None
+    }
+}
+
 fn occ(accessor: &dyn Fn(usize, usize) -> u8) -> [u8; 10] {
     let mut result: [u8; 10] = [0; 10];
     for i in MIN_INDEX..=MAX_INDEX {
@@ -343,11 +576,84 @@ fn reverse_cols(setup: Setup) -> Setup {
     result
 }
 
+// Legal fleets for property tests: each ship goes to the first free place at
+// or after a random cell and is numbered in the order of the fleet.
+#[cfg(test)]
+pub fn arb_setup() -> impl proptest::strategy::Strategy<Value = Setup> {
+    use proptest::prelude::*;
+    proptest::collection::vec((0..100usize, any::<bool>()), FLEET.len())
+        .prop_filter_map("the fleet does not fit", |places| place_fleet(&places))
+}
+
+// Numbered 1..=20 without touching corners, but with two ships of four.
+#[cfg(test)]
+pub fn two_battleships() -> Setup {
+    let cells = [
+        (0, 0), (0, 1), (0, 2), (0, 3),
+        (2, 0), (2, 1), (2, 2), (2, 3),
+        (4, 0), (4, 1), (4, 3), (4, 4), (4, 6), (4, 7), (6, 0), (6, 1),
+        (8, 0), (8, 2), (8, 4), (8, 6),
+    ];
+    let mut result: Setup = [[0; 10]; 10];
+    for (k, &(r, c)) in cells.iter().enumerate() {
+        result[r][c] = k as u8 + 1;
+    }
+    result
+}
+
+#[cfg(test)]
+fn place_fleet(places: &[(usize, bool)]) -> Option<Setup> {
+    let mut result: Setup = [[0; 10]; 10];
+    let mut value = 0;
+    for (size, &(start, vertical)) in FLEET.iter().zip(places) {
+        let free = |r: usize, c: usize| {
+            let around = |i: usize| i.saturating_sub(1)..=(i + 1).min(MAX_INDEX);
+            r <= MAX_INDEX
+                && c <= MAX_INDEX
+                && around(r).all(|i| around(c).all(|j| result[i][j] == 0))
+        };
+        let ship: Vec<(usize, usize)> = (0..100)
+            .map(|k| (start + k) % 100)
+            .map(|p| {
+                (0..*size)
+                    .map(|k| if vertical { (p / 10 + k, p % 10) } else { (p / 10, p % 10 + k) })
+                    .collect::<Vec<(usize, usize)>>()
+            })
+            .find(|cells| cells.iter().all(|&(r, c)| free(r, c)))?;
+        for (r, c) in ship {
+            value += 1;
+            result[r][c] = value;
+        }
+    }
+    Some(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use test_case::test_case;
     use itertools::Itertools;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn prop_counts_sum_to_20(setup in arb_setup()) {
+            prop_assert!(is_valid(&setup));
+            prop_assert_eq!(setup.occupied_rows().iter().map(|n| *n as usize).sum::<usize>(), 20);
+            prop_assert_eq!(setup.occupied_cols().iter().map(|n| *n as usize).sum::<usize>(), 20);
+        }
+
+        #[test]
+        fn prop_symmetries_keep_legality(setup in arb_setup()) {
+            for symmetry in [transpose, reverse_rows, reverse_cols] {
+                let moved = symmetry(setup);
+                prop_assert!(is_valid(&moved));
+                prop_assert!(from_cells(&cells(&moved)).is_some());
+            }
+            prop_assert_eq!(transpose(setup).occupied_rows(), setup.occupied_cols());
+            prop_assert_eq!(reverse_rows(reverse_rows(setup)), setup);
+        }
+    }
 
     #[test]
     fn test_quantity() {
@@ -365,6 +671,92 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_is_valid() {
+        for sf in build_all().values() {
+            assert!(is_valid(&sf.setup));
+        }
+        let mut touching = GAME_0;
+        touching[1][0] = touching[3][0];
+        touching[3][0] = 0;
+        assert!(!is_valid(&touching));
+        let mut missing = GAME_0;
+        missing[9][1] = 0;
+        assert!(!is_valid(&missing));
+    }
+
+    #[test]
+    fn test_from_cells() {
+        for sf in build_all().values() {
+            let setup = from_cells(&cells(&sf.setup)).unwrap();
+            assert_eq!(cells(&setup), cells(&sf.setup));
+        }
+        let l_shaped = [(0, 0), (1, 0), (1, 1)];
+        assert_eq!(from_cells(&l_shaped), None);
+        let mut too_few: Vec<(usize, usize)> = (0..10).map(|j| (0, j)).collect();
+        too_few.truncate(4);
+        assert_eq!(from_cells(&too_few), None);
+        assert_eq!(ship_of(1), Some(0));
+        assert_eq!(ship_of(7), Some(1));
+        assert_eq!(ship_of(20), Some(9));
+        assert_eq!(ship_of(21), None);
+    }
+
+    #[test]
+    fn test_catalog_round_trip() {
+        let yaml = serde_yaml::to_string(&to_catalog(&build_all())).unwrap();
+        let entries = serde_yaml::from_str::<Vec<CatalogEntry>>(&yaml).unwrap();
+        assert_eq!(from_catalog(entries).unwrap(), build_all());
+        // catalogs written before the rename still load
+        let renamed = yaml.replace("irreducible_hints", "minimal_hints");
+        assert_eq!(
+            serde_yaml::from_str::<Vec<CatalogEntry>>(&renamed).unwrap(),
+            to_catalog(&build_all())
+        );
+
+        let mut broken = to_catalog(&build_all());
+        broken[0].setup[0][0] = 21;
+        assert!(from_catalog(broken).is_err());
+
+        let mut wrong_fleet = to_catalog(&build_all());
+        wrong_fleet[0].setup = two_battleships();
+        wrong_fleet[0].irreducible_hints = None;
+        assert!(is_valid(&wrong_fleet[0].setup));
+        assert!(!has_fleet(&wrong_fleet[0].setup));
+        assert!(from_catalog(wrong_fleet).is_err());
+
+        // one hint short of a unique solution, and one to spare
+        let mut entries = to_catalog(&build_all());
+        entries.truncate(1);
+        let hints = build_all()[&entries[0].id].irreducible_hints.unwrap();
+        let fewer = Reveals(hints.0 & (hints.0 - 1));
+        let spare = cells(&entries[0].setup)
+            .into_iter()
+            .map(|(r, c)| Reveals::of(&[(r, c)]))
+            .find(|cell| cell.0 & hints.0 == 0)
+            .unwrap();
+        for reveals in [fewer, Reveals(hints.0 | spare.0)] {
+            entries[0].irreducible_hints = Some(reveals.into());
+            assert!(from_catalog(entries.clone()).is_err());
+        }
+
+        let mut hints: Vec<Coord> = hints.into();
+        hints.push(Coord { row: 3, col: 12 });
+        entries[0].irreducible_hints = Some(hints);
+        let err = from_catalog(entries.clone()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("3,12"));
+
+        entries[0].irreducible_hints = Some(
+            cells(&entries[0].setup)
+                .into_iter()
+                .map(|(row, col)| Coord { row, col })
+                .collect(),
+        );
+        let err = from_catalog(entries).unwrap_err();
+        assert!(err.to_string().contains("more than"));
+    }
+
     #[test]
     fn test_transpose() {
         let result: Setup = [
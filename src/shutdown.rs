@@ -0,0 +1,79 @@
+use crate::{State, CT_PLAIN};
+use async_std::stream::StreamExt;
+use async_std::task;
+use signal_hook::consts::signal::{SIGINT, SIGTERM};
+use signal_hook_async_std::Signals;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tide::{Middleware, Next, Request, Response};
+
+const DEFAULT_DEADLINE_SECS: u64 = 30;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub fn deadline() -> Duration {
+    let secs = std::env::var("BIMARU_SHUTDOWN_TIMEOUT")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_DEADLINE_SECS);
+    Duration::from_secs(secs)
+}
+
+pub async fn wait_for_signal() -> std::io::Result<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
+    signals.next().await;
+    Ok(())
+}
+
+pub async fn drain(state: &State, deadline: Duration) -> bool {
+    let started = Instant::now();
+    while state.in_flight.load(Ordering::SeqCst) > 0 {
+        if started.elapsed() >= deadline {
+            return false;
+        }
+        task::sleep(POLL_INTERVAL).await;
+    }
+    true
+}
+
+// Resolves once the server is stopping. Event streams and live games end
+// then instead of running until the process is cut off.
+pub async fn stopped(state: &State) {
+    while !state.stopping.load(Ordering::SeqCst) {
+        task::sleep(POLL_INTERVAL).await;
+    }
+}
+
+// The probes keep answering while stopping, so the pod turns unready
+// instead of looking dead.
+const PROBES: [&str; 2] = ["/healthz", "/readyz"];
+
+pub struct InFlight;
+
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[tide::utils::async_trait]
+impl Middleware<State> for InFlight {
+    async fn handle(&self, req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        if PROBES.contains(&req.url().path()) {
+            return Ok(next.run(req).await);
+        }
+        let state = req.state();
+        if state.stopping.load(Ordering::SeqCst) {
+            return Ok(Response::builder(503)
+                .body("Server is shutting down")
+                .header("Connection", "close")
+                .content_type(CT_PLAIN)
+                .build());
+        }
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        let _guard = InFlightGuard(state.in_flight.clone());
+        Ok(next.run(req).await)
+    }
+}
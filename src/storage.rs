@@ -0,0 +1,655 @@
+use crate::roster::{self, Assignment, Roster};
+use crate::tournament::{self, Tournament};
+use im::HashMap;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+pub type Migration = fn(serde_yaml::Value) -> serde_yaml::Value;
+
+// MIGRATIONS[n] upgrades a stored event from schema version n + 1 to n + 2
+const MIGRATIONS: &[Migration] = &[
+    // 2 adds the tournament and roster events and the team tokens
+    unchanged,
+];
+
+fn unchanged(value: serde_yaml::Value) -> serde_yaml::Value {
+    value
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Session {
+    #[serde(with = "crate::uuid_as_string")]
+    pub id: Uuid,
+    #[serde(with = "crate::uuid_as_string")]
+    pub setup_id: Uuid,
+    pub player: Option<String>,
+    pub started_at: u64,
+    pub hints_used: u8,
+    pub check_attempts: u32,
+    pub completed_at: Option<u64>,
+    // the token of the tournament team the session plays for
+    #[serde(default)]
+    pub team_token: Option<Uuid>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "event")]
+pub enum Event {
+    SessionStarted {
+        #[serde(with = "crate::uuid_as_string")]
+        session_id: Uuid,
+        #[serde(with = "crate::uuid_as_string")]
+        setup_id: Uuid,
+        #[serde(default)]
+        player: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team_token: Option<Uuid>,
+        at: u64,
+    },
+    HintServed {
+        #[serde(with = "crate::uuid_as_string")]
+        session_id: Uuid,
+        hints: u8,
+        at: u64,
+    },
+    CheckAttempted {
+        #[serde(with = "crate::uuid_as_string")]
+        session_id: Uuid,
+        solved: bool,
+        at: u64,
+    },
+    TournamentCreated {
+        #[serde(with = "crate::uuid_as_string")]
+        tournament_id: Uuid,
+        name: String,
+        setups: Vec<Uuid>,
+        starts_at: u64,
+        round_millis: u64,
+        at: u64,
+    },
+    TeamRegistered {
+        #[serde(with = "crate::uuid_as_string")]
+        tournament_id: Uuid,
+        team: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<Uuid>,
+        at: u64,
+    },
+    RosterUploaded {
+        assignments: Vec<Assignment>,
+        at: u64,
+    },
+}
+
+pub trait Storage: Send + Sync {
+    fn record(&self, event: Event) -> io::Result<()>;
+    fn session(&self, id: &Uuid) -> Option<Session>;
+    fn sessions(&self) -> Vec<Session>;
+    fn tournament(&self, id: &Uuid) -> Option<Tournament>;
+    fn roster(&self) -> Roster;
+    // the team of a tournament a token was issued to
+    fn team(&self, token: &Uuid) -> Option<String>;
+    // every event recorded so far, oldest first
+    fn for_each_event(&self, visit: &mut dyn FnMut(&Event)) -> io::Result<()>;
+    fn flush(&self) -> io::Result<()>;
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Sessions, tournaments and the roster folded from the events as they are
+// recorded.
+#[derive(Default)]
+pub(crate) struct Sessions {
+    by_id: HashMap<Uuid, Session>,
+    tournaments: HashMap<Uuid, Tournament>,
+    roster: Roster,
+}
+
+impl Sessions {
+    pub(crate) fn apply(&mut self, event: &Event) {
+        match *event {
+            Event::SessionStarted {
+                session_id,
+                setup_id,
+                ref player,
+                team_token,
+                at,
+            } => {
+                self.by_id.insert(
+                    session_id,
+                    Session {
+                        id: session_id,
+                        setup_id,
+                        player: player.clone(),
+                        started_at: at,
+                        hints_used: 0,
+                        check_attempts: 0,
+                        completed_at: None,
+                        team_token,
+                    },
+                );
+            }
+            Event::HintServed {
+                session_id, hints, ..
+            } => {
+                // hints fetched after solving do not count against the score
+                if let Some(s) = self.by_id.get_mut(&session_id) {
+                    if s.completed_at.is_none() {
+                        s.hints_used = s.hints_used.max(hints);
+                    }
+                }
+            }
+            Event::CheckAttempted {
+                session_id,
+                solved,
+                at,
+            } => {
+                if let Some(s) = self.by_id.get_mut(&session_id) {
+                    s.check_attempts += 1;
+                    if solved && s.completed_at.is_none() {
+                        s.completed_at = Some(at);
+                    }
+                }
+            }
+            Event::TournamentCreated { .. } | Event::TeamRegistered { .. } => {
+                tournament::apply(&mut self.tournaments, event);
+            }
+            Event::RosterUploaded { .. } => roster::apply(&mut self.roster, event),
+        }
+    }
+
+    pub(crate) fn team(&self, token: &Uuid) -> Option<String> {
+        self.tournaments
+            .values()
+            .find_map(|t| t.team_of(token).map(|team| team.to_string()))
+    }
+
+    pub(crate) fn get(&self, id: &Uuid) -> Option<&Session> {
+        self.by_id.get(id)
+    }
+
+    pub(crate) fn list(&self) -> Vec<Session> {
+        let mut result: Vec<Session> = self.by_id.values().cloned().collect();
+        result.sort_by_key(|s| (s.started_at, s.id));
+        result
+    }
+}
+
+#[derive(Default)]
+struct Memory {
+    sessions: Sessions,
+    events: Vec<Event>,
+}
+
+// Keeps every event, the memory is all it has to replay them from.
+#[derive(Default)]
+pub struct MemoryStorage {
+    memory: Mutex<Memory>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn record(&self, event: Event) -> io::Result<()> {
+        let mut memory = self.memory.lock().unwrap();
+        memory.sessions.apply(&event);
+        memory.events.push(event);
+        Ok(())
+    }
+
+    fn session(&self, id: &Uuid) -> Option<Session> {
+        self.memory.lock().unwrap().sessions.by_id.get(id).cloned()
+    }
+
+    fn sessions(&self) -> Vec<Session> {
+        self.memory.lock().unwrap().sessions.list()
+    }
+
+    fn tournament(&self, id: &Uuid) -> Option<Tournament> {
+        let memory = self.memory.lock().unwrap();
+        memory.sessions.tournaments.get(id).cloned()
+    }
+
+    fn roster(&self) -> Roster {
+        self.memory.lock().unwrap().sessions.roster.clone()
+    }
+
+    fn team(&self, token: &Uuid) -> Option<String> {
+        self.memory.lock().unwrap().sessions.team(token)
+    }
+
+    fn for_each_event(&self, visit: &mut dyn FnMut(&Event)) -> io::Result<()> {
+        let events = self.memory.lock().unwrap().events.clone();
+        events.iter().for_each(visit);
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    schema_version: u32,
+}
+
+struct Log {
+    sessions: Sessions,
+    writer: BufWriter<File>,
+}
+
+// Append-only log of YAML documents: a header with the schema version
+// followed by one document per event. Replays read the events back from the
+// file.
+pub struct FileStorage {
+    path: PathBuf,
+    log: Mutex<Log>,
+}
+
+impl FileStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FileStorage> {
+        let path = path.as_ref().to_path_buf();
+        let mut content = String::new();
+        if path.exists() {
+            File::open(&path)?.read_to_string(&mut content)?;
+        }
+        let events = if content.trim().is_empty() {
+            write_log(&path, &[])?;
+            vec![]
+        } else {
+            let (version, values, torn) = parse_log(&content)?;
+            let events = migrate(version, values, MIGRATIONS)?;
+            if version != SCHEMA_VERSION || torn {
+                write_log(&path, &events)?;
+            }
+            events
+        };
+        let mut sessions = Sessions::default();
+        for e in events.iter() {
+            sessions.apply(e);
+        }
+        let file = OpenOptions::new().append(true).open(&path)?;
+        Ok(FileStorage {
+            path,
+            log: Mutex::new(Log {
+                sessions,
+                writer: BufWriter::new(file),
+            }),
+        })
+    }
+}
+
+impl Storage for FileStorage {
+    fn record(&self, event: Event) -> io::Result<()> {
+        let mut log = self.log.lock().unwrap();
+        write_document(&mut log.writer, &event)?;
+        log.writer.flush()?;
+        log.sessions.apply(&event);
+        Ok(())
+    }
+
+    fn session(&self, id: &Uuid) -> Option<Session> {
+        self.log.lock().unwrap().sessions.by_id.get(id).cloned()
+    }
+
+    fn sessions(&self) -> Vec<Session> {
+        self.log.lock().unwrap().sessions.list()
+    }
+
+    fn tournament(&self, id: &Uuid) -> Option<Tournament> {
+        let log = self.log.lock().unwrap();
+        log.sessions.tournaments.get(id).cloned()
+    }
+
+    fn roster(&self) -> Roster {
+        self.log.lock().unwrap().sessions.roster.clone()
+    }
+
+    fn team(&self, token: &Uuid) -> Option<String> {
+        self.log.lock().unwrap().sessions.team(token)
+    }
+
+    // the lock keeps records out only while the file is read, the events are
+    // parsed and visited after it is released
+    fn for_each_event(&self, visit: &mut dyn FnMut(&Event)) -> io::Result<()> {
+        let mut content = vec![];
+        {
+            let _log = self.log.lock().unwrap();
+            File::open(&self.path)?.read_to_end(&mut content)?;
+        }
+        let mut header = true;
+        for_each_document(content.as_slice(), &mut |document| {
+            if !std::mem::take(&mut header) {
+                visit(&serde_yaml::from_str(document).map_err(invalid_data)?);
+            }
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let mut log = self.log.lock().unwrap();
+        log.writer.flush()?;
+        log.writer.get_ref().sync_all()
+    }
+}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn write_document<W: Write, T: Serialize>(writer: &mut W, value: &T) -> io::Result<()> {
+    let yaml = serde_yaml::to_string(value).map_err(invalid_data)?;
+    writer.write_all(b"---\n")?;
+    writer.write_all(yaml.as_bytes())
+}
+
+fn write_log(path: &Path, events: &[Event]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    write_document(
+        &mut writer,
+        &Header {
+            schema_version: SCHEMA_VERSION,
+        },
+    )?;
+    for e in events {
+        write_document(&mut writer, e)?;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    std::fs::rename(tmp, path)
+}
+
+// Reads the log one document at a time, so no more than one is in memory.
+fn for_each_document<R: BufRead>(
+    reader: R,
+    visit: &mut dyn FnMut(&str) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut document = String::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line == "---" {
+            if !document.is_empty() {
+                visit(&document)?;
+            }
+            document.clear();
+        } else {
+            document.push_str(&line);
+            document.push('\n');
+        }
+    }
+    if document.is_empty() {
+        Ok(())
+    } else {
+        visit(&document)
+    }
+}
+
+// A crash while appending can leave the last event torn. It was never
+// acknowledged, so it is dropped and reported for the log to be rewritten.
+// A log cut short of a newline or a last document that is not YAML is torn,
+// an event of an unknown shape is an error.
+fn parse_log(content: &str) -> io::Result<(u32, Vec<serde_yaml::Value>, bool)> {
+    // every complete document ends in a newline, a cut inside one drops all of it
+    let complete = if content.ends_with('\n') {
+        content
+    } else {
+        let tail = content.rfind('\n').map_or(0, |end| end + 1);
+        if "---".starts_with(&content[tail..]) {
+            &content[..tail]
+        } else {
+            match content[..tail].rfind("\n---\n") {
+                Some(end) => &content[..=end],
+                None => "",
+            }
+        }
+    };
+    let mut torn = complete.len() < content.len();
+    let mut documents = vec![];
+    for_each_document(complete.as_bytes(), &mut |d| {
+        documents.push(d.to_string());
+        Ok(())
+    })?;
+    let header = match documents.first() {
+        Some(d) => serde_yaml::from_str::<Header>(d).map_err(invalid_data)?,
+        None => return Err(invalid_data("Missing log header")),
+    };
+    let mut values = vec![];
+    for (i, d) in documents.iter().enumerate().skip(1) {
+        match serde_yaml::from_str::<serde_yaml::Value>(d) {
+            Ok(value) => values.push(value),
+            Err(_) if i == documents.len() - 1 => torn = true,
+            Err(e) => return Err(invalid_data(e)),
+        }
+    }
+    Ok((header.schema_version, values, torn))
+}
+
+fn migrate(
+    version: u32,
+    values: Vec<serde_yaml::Value>,
+    migrations: &[Migration],
+) -> io::Result<Vec<Event>> {
+    let target = migrations.len() as u32 + 1;
+    if version == 0 || version > target {
+        return Err(invalid_data(format!(
+            "Unsupported schema version {}",
+            version
+        )));
+    }
+    let steps = &migrations[(version - 1) as usize..];
+    values
+        .into_iter()
+        .map(|v| steps.iter().fold(v, |acc, m| m(acc)))
+        .map(|v| serde_yaml::from_value::<Event>(v).map_err(invalid_data))
+        .collect()
+}
+
+#[cfg(test)]
+pub fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("bimaru-{}-{}.log", name, Uuid::new_v4()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started(session_id: Uuid) -> Event {
+        Event::SessionStarted {
+            session_id,
+            setup_id: uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b"),
+            player: Some("alice".to_string()),
+            team_token: None,
+            at: 1,
+        }
+    }
+
+    #[test]
+    fn test_file_storage_reopen() {
+        let path = temp_path("reopen");
+        let id = Uuid::new_v4();
+        {
+            let storage = FileStorage::open(&path).unwrap();
+            storage.record(started(id)).unwrap();
+            storage
+                .record(Event::HintServed {
+                    session_id: id,
+                    hints: 3,
+                    at: 2,
+                })
+                .unwrap();
+            storage
+                .record(Event::CheckAttempted {
+                    session_id: id,
+                    solved: true,
+                    at: 5,
+                })
+                .unwrap();
+        }
+        let storage = FileStorage::open(&path).unwrap();
+        let session = storage.session(&id).unwrap();
+        assert_eq!(session.hints_used, 3);
+        assert_eq!(session.check_attempts, 1);
+        assert_eq!(session.completed_at, Some(5));
+        assert_eq!(session.player, Some("alice".to_string()));
+        assert_eq!(storage.sessions(), vec![session]);
+        let mut events = vec![];
+        storage
+            .for_each_event(&mut |e| events.push(e.clone()))
+            .unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0], started(id));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_hints_after_completion() {
+        let storage = MemoryStorage::new();
+        let id = Uuid::new_v4();
+        storage.record(started(id)).unwrap();
+        storage
+            .record(Event::CheckAttempted {
+                session_id: id,
+                solved: true,
+                at: 2,
+            })
+            .unwrap();
+        storage
+            .record(Event::HintServed {
+                session_id: id,
+                hints: 3,
+                at: 3,
+            })
+            .unwrap();
+        let session = storage.session(&id).unwrap();
+        assert_eq!(session.hints_used, 0);
+        assert_eq!(session.completed_at, Some(2));
+    }
+
+    #[test]
+    fn test_tournaments_survive_reopen() {
+        let path = temp_path("tournaments");
+        let tournament_id = Uuid::new_v4();
+        let token = Uuid::new_v4();
+        {
+            let storage = FileStorage::open(&path).unwrap();
+            storage
+                .record(Event::TournamentCreated {
+                    tournament_id,
+                    name: "club".to_string(),
+                    setups: vec![Uuid::new_v4()],
+                    starts_at: 0,
+                    round_millis: 1000,
+                    at: 1,
+                })
+                .unwrap();
+            storage
+                .record(Event::TeamRegistered {
+                    tournament_id,
+                    team: "reds".to_string(),
+                    token: Some(token),
+                    at: 2,
+                })
+                .unwrap();
+        }
+        let storage = FileStorage::open(&path).unwrap();
+        let tournament = storage.tournament(&tournament_id).unwrap();
+        assert_eq!(tournament.spec.name, "club");
+        assert_eq!(tournament.teams, vec!["reds"]);
+        assert_eq!(storage.team(&token), Some("reds".to_string()));
+        assert_eq!(storage.tournament(&Uuid::new_v4()), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_last_event() {
+        let id = Uuid::new_v4();
+        let mut log = "---\nschema_version: 1\n".to_string();
+        log.push_str(&format!(
+            "---\n{}",
+            serde_yaml::to_string(&started(id)).unwrap()
+        ));
+        let path = temp_path("torn");
+        for torn in [
+            "---\nevent: HintServed\nsession_id: ",
+            "---\nevent: [Hint",
+            "---\nevent: [Hint\n",
+            "--",
+        ] {
+            std::fs::write(&path, format!("{}{}", log, torn)).unwrap();
+            {
+                let storage = FileStorage::open(&path).unwrap();
+                assert_eq!(storage.sessions().len(), 1, "{}", torn);
+                storage.record(started(Uuid::new_v4())).unwrap();
+            }
+            // the rewritten log takes appends again
+            assert_eq!(FileStorage::open(&path).unwrap().sessions().len(), 2);
+        }
+        // only the last document may be broken
+        std::fs::write(&path, format!("{}---\nevent: [\n{}", log, log)).unwrap();
+        assert!(FileStorage::open(&path).is_err());
+        // and an event this version does not know is not torn
+        std::fs::write(&path, format!("{}---\nevent: HintServed\n", log)).unwrap();
+        assert!(FileStorage::open(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let path = temp_path("version");
+        std::fs::write(&path, "---\nschema_version: 99\n").unwrap();
+        assert!(FileStorage::open(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_migrate() {
+        fn rename_event(mut v: serde_yaml::Value) -> serde_yaml::Value {
+            if v["event"] == "Started" {
+                v["event"] = "SessionStarted".into();
+            }
+            v
+        }
+        let id = Uuid::new_v4();
+        let mut legacy = serde_yaml::to_value(started(id)).unwrap();
+        legacy["event"] = "Started".into();
+        let events = migrate(1, vec![legacy.clone()], &[rename_event]).unwrap();
+        assert_eq!(events, vec![started(id)]);
+        assert!(migrate(1, vec![legacy], &[]).is_err());
+
+        // a version 1 log, written before team tokens, is upgraded on open
+        let mut v1 = serde_yaml::to_value(started(id)).unwrap();
+        v1.as_mapping_mut().unwrap().remove("team_token");
+        let path = temp_path("v1");
+        std::fs::write(
+            &path,
+            format!(
+                "---\nschema_version: 1\n---\n{}",
+                serde_yaml::to_string(&v1).unwrap()
+            ),
+        )
+        .unwrap();
+        assert_eq!(FileStorage::open(&path).unwrap().sessions().len(), 1);
+        let (version, values, _) = parse_log(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!((version, values.len()), (SCHEMA_VERSION, 1));
+        assert_eq!(SCHEMA_VERSION, 2);
+        std::fs::remove_file(path).unwrap();
+    }
+}
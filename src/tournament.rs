@@ -0,0 +1,545 @@
+use crate::admin::{forbidden, is_admin};
+use crate::storage::{now_millis, Event, Session, Sessions, Storage};
+use crate::{
+    conflict, illegal_request, not_found, unprocessable, yaml_response, State, CT_PLAIN, CT_YAML,
+};
+use im::HashMap;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::str::FromStr;
+use tide::{Request, Response};
+use uuid::Uuid;
+
+const SOLVED_POINTS: u64 = 100;
+const HINT_PENALTY: u64 = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct NewTournament {
+    pub name: String,
+    pub setups: Vec<Uuid>,
+    pub starts_at: u64,
+    pub round_millis: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NewTeam {
+    pub name: String,
+}
+
+// A team plays as the player of its name, with the token it got on
+// registration; teams stored before tokens were issued have none.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RegisteredTeam {
+    pub name: String,
+    #[serde(with = "crate::uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub token: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tournament {
+    pub id: Uuid,
+    pub spec: NewTournament,
+    pub teams: Vec<String>,
+    pub tokens: HashMap<String, Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Standing {
+    pub team: String,
+    pub score: u64,
+    pub rounds_solved: usize,
+    pub hints_used: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct TournamentStatus {
+    #[serde(with = "crate::uuid_as_string")]
+    #[schemars(with = "Uuid")]
+    pub tournament_id: Uuid,
+    pub name: String,
+    pub setups: Vec<Uuid>,
+    pub starts_at: u64,
+    pub round_millis: u64,
+    pub teams: Vec<String>,
+    // the round being played, none before the start and after the end
+    pub current_round: Option<usize>,
+    pub finished: bool,
+    pub standings: Vec<Standing>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct ReplayFrame {
+    pub at: u64,
+    pub standings: Vec<Standing>,
+}
+
+impl Tournament {
+    // create refuses tournaments ending past u64::MAX, but a log may hold one
+    // recorded before it did
+    fn round_window(&self, round: usize) -> (u64, u64) {
+        let start = (round as u64)
+            .saturating_mul(self.spec.round_millis)
+            .saturating_add(self.spec.starts_at);
+        (start, start.saturating_add(self.spec.round_millis))
+    }
+
+    fn ends_at(&self) -> u64 {
+        self.round_window(self.spec.setups.len()).0
+    }
+
+    pub fn current_round(&self, now: u64) -> Option<usize> {
+        if now < self.spec.starts_at || now >= self.ends_at() {
+            None
+        } else {
+            Some(((now - self.spec.starts_at) / self.spec.round_millis) as usize)
+        }
+    }
+
+    // A round counts when the team started and solved the round's setup
+    // within its time; the fastest solve scores, minus the hints it used.
+    pub fn team_of(&self, token: &Uuid) -> Option<&str> {
+        self.tokens
+            .iter()
+            .find(|(_, t)| *t == token)
+            .map(|(team, _)| team.as_str())
+    }
+
+    // a session counts towards the standings only when a team plays one of
+    // the tournament's setups with its token
+    fn entered(&self, session: &Session) -> bool {
+        self.spec.setups.contains(&session.setup_id)
+            && session.player.as_ref().is_some_and(|p| {
+                self.teams.contains(p) && self.tokens.get(p) == session.team_token.as_ref()
+            })
+    }
+
+    fn round_score(&self, team: &str, round: usize, sessions: &[&Session]) -> Option<(u64, u8)> {
+        let (start, end) = self.round_window(round);
+        sessions
+            .iter()
+            .filter(|s| s.player.as_deref() == Some(team))
+            .filter(|s| s.setup_id == self.spec.setups[round])
+            .filter(|s| s.started_at >= start && s.started_at < end)
+            .filter_map(|s| match s.completed_at {
+                Some(done) if done < end => {
+                    let points = SOLVED_POINTS + (end - done) / 1000;
+                    Some((
+                        points.saturating_sub(HINT_PENALTY * s.hints_used as u64),
+                        s.hints_used,
+                    ))
+                }
+                _ => None,
+            })
+            .max()
+    }
+
+    pub fn standings(&self, sessions: &[Session]) -> Vec<Standing> {
+        let sessions: Vec<&Session> = sessions.iter().filter(|s| self.entered(s)).collect();
+        let mut result: Vec<Standing> = self
+            .teams
+            .iter()
+            .map(|team| {
+                let rounds: Vec<(u64, u8)> = (0..self.spec.setups.len())
+                    .filter_map(|round| self.round_score(team, round, &sessions))
+                    .collect();
+                Standing {
+                    team: team.clone(),
+                    score: rounds.iter().map(|r| r.0).sum(),
+                    rounds_solved: rounds.len(),
+                    hints_used: rounds.iter().map(|r| r.1 as u32).sum(),
+                }
+            })
+            .collect();
+        result.sort_by(|a, b| (b.score, &a.team).cmp(&(a.score, &b.team)));
+        result
+    }
+
+    pub fn status(&self, sessions: &[Session], now: u64) -> TournamentStatus {
+        TournamentStatus {
+            tournament_id: self.id,
+            name: self.spec.name.clone(),
+            setups: self.spec.setups.clone(),
+            starts_at: self.spec.starts_at,
+            round_millis: self.spec.round_millis,
+            teams: self.teams.clone(),
+            current_round: self.current_round(now),
+            finished: now >= self.ends_at(),
+            standings: self.standings(sessions),
+        }
+    }
+}
+
+// Tournaments and their teams are stored as events like the games, the
+// storage folds them in as they are recorded.
+pub(crate) fn apply(tournaments: &mut HashMap<Uuid, Tournament>, event: &Event) {
+    match event {
+        Event::TournamentCreated {
+            tournament_id,
+            name,
+            setups,
+            starts_at,
+            round_millis,
+            ..
+        } => {
+            tournaments.insert(
+                *tournament_id,
+                Tournament {
+                    id: *tournament_id,
+                    spec: NewTournament {
+                        name: name.clone(),
+                        setups: setups.clone(),
+                        starts_at: *starts_at,
+                        round_millis: *round_millis,
+                    },
+                    teams: vec![],
+                    tokens: HashMap::new(),
+                },
+            );
+        }
+        Event::TeamRegistered {
+            tournament_id,
+            team,
+            token,
+            ..
+        } => {
+            if let Some(t) = tournaments.get_mut(tournament_id) {
+                t.teams.push(team.clone());
+                if let Some(token) = token {
+                    t.tokens.insert(team.clone(), *token);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// The standings after every solve that changed them, replayed from the log.
+// Only solved sessions of the tournament's teams and setups are kept, as
+// later hints no longer change a solved session.
+pub fn replay(tournament: &Tournament, storage: &dyn Storage) -> io::Result<Vec<ReplayFrame>> {
+    let mut sessions = Sessions::default();
+    let mut solved: HashMap<Uuid, Session> = HashMap::new();
+    let mut frames: Vec<ReplayFrame> = vec![];
+    let mut last = tournament.standings(&[]);
+    storage.for_each_event(&mut |e| {
+        sessions.apply(e);
+        if let Event::CheckAttempted {
+            session_id,
+            solved: true,
+            at,
+        } = e
+        {
+            if let Some(session) = sessions.get(session_id).filter(|s| tournament.entered(s)) {
+                solved.insert(*session_id, session.clone());
+                let entrants: Vec<Session> = solved.values().cloned().collect();
+                let standings = tournament.standings(&entrants);
+                if standings != last {
+                    last = standings.clone();
+                    frames.push(ReplayFrame { at: *at, standings });
+                }
+            }
+        }
+    })?;
+    Ok(frames)
+}
+
+pub fn wrong_team() -> tide::Result {
+    Ok(Response::builder(403)
+        .body("Team token does not belong to the player")
+        .content_type(CT_PLAIN)
+        .build())
+}
+
+fn tournament_of(req: &Request<State>) -> Option<Tournament> {
+    let id = Uuid::from_str(req.param("tournament_id").ok()?).ok()?;
+    req.state().storage.tournament(&id)
+}
+
+pub async fn create(mut req: Request<State>) -> tide::Result {
+    if !is_admin(&req) {
+        return forbidden();
+    }
+    let body_str = req.body_string().await?;
+    if let Ok(spec) = serde_yaml::from_str::<NewTournament>(&body_str) {
+        if spec.setups.is_empty() || spec.round_millis == 0 {
+            return unprocessable("A tournament needs setups and a round time");
+        }
+        // round_window must not overflow for any round
+        let length = (spec.setups.len() as u64).checked_mul(spec.round_millis);
+        if length.and_then(|l| l.checked_add(spec.starts_at)).is_none() {
+            return unprocessable("The tournament ends too late");
+        }
+        if !spec.setups.iter().all(|id| req.state().setup(id).is_some()) {
+            return unprocessable("Unknown game setup");
+        }
+        let tournament = Tournament {
+            id: Uuid::new_v4(),
+            spec,
+            teams: vec![],
+            tokens: HashMap::new(),
+        };
+        req.state().storage.record(Event::TournamentCreated {
+            tournament_id: tournament.id,
+            name: tournament.spec.name.clone(),
+            setups: tournament.spec.setups.clone(),
+            starts_at: tournament.spec.starts_at,
+            round_millis: tournament.spec.round_millis,
+            at: now_millis(),
+        })?;
+        yaml_response(201, CT_YAML, &tournament.status(&[], now_millis()))
+    } else {
+        illegal_request("Could not parse entity")
+    }
+}
+
+pub async fn register(mut req: Request<State>) -> tide::Result {
+    let body_str = req.body_string().await?;
+    if let Some(tournament) = tournament_of(&req) {
+        if let Ok(entity) = serde_yaml::from_str::<NewTeam>(&body_str) {
+            if entity.name.is_empty() {
+                unprocessable("A team needs a name")
+            } else if tournament.teams.contains(&entity.name) {
+                conflict("Team is already registered")
+            } else {
+                let team = RegisteredTeam {
+                    name: entity.name,
+                    token: Uuid::new_v4(),
+                };
+                req.state().storage.record(Event::TeamRegistered {
+                    tournament_id: tournament.id,
+                    team: team.name.clone(),
+                    token: Some(team.token),
+                    at: now_millis(),
+                })?;
+                yaml_response(201, CT_YAML, &team)
+            }
+        } else {
+            illegal_request("Could not parse entity")
+        }
+    } else {
+        not_found("Unknown tournament")
+    }
+}
+
+pub async fn show(req: Request<State>) -> tide::Result {
+    if let Some(tournament) = tournament_of(&req) {
+        let sessions = req.state().storage.sessions();
+        yaml_response(200, CT_YAML, &tournament.status(&sessions, now_millis()))
+    } else {
+        not_found("Unknown tournament")
+    }
+}
+
+pub async fn show_replay(req: Request<State>) -> tide::Result {
+    if let Some(tournament) = tournament_of(&req) {
+        let frames = replay(&tournament, req.state().storage.as_ref())?;
+        yaml_response(200, CT_YAML, &frames)
+    } else {
+        not_found("Unknown tournament")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_app_from;
+    use crate::setups::{build_all, cells};
+    use crate::storage::MemoryStorage;
+    use crate::{Check, SESSION_HEADER};
+    use std::sync::Arc;
+    use tide_testing::TideTestingExt;
+
+    const A: Uuid = uuid::uuid!("dd8fb490-72c8-485b-aeea-537b9be34e4b");
+    const B: Uuid = uuid::uuid!("37073150-f43d-4609-94ec-dcbeffcb472a");
+
+    fn session(team: &str, setup_id: Uuid, started_at: u64, done: Option<u64>) -> Session {
+        Session {
+            id: Uuid::new_v4(),
+            setup_id,
+            player: Some(team.to_string()),
+            started_at,
+            hints_used: 2,
+            check_attempts: 1,
+            completed_at: done,
+            team_token: None,
+        }
+    }
+
+    fn tournament() -> Tournament {
+        Tournament {
+            id: Uuid::new_v4(),
+            spec: NewTournament {
+                name: "club".to_string(),
+                setups: vec![A, B],
+                starts_at: 10_000,
+                round_millis: 60_000,
+            },
+            teams: vec!["reds".to_string(), "blues".to_string()],
+            tokens: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_standings() {
+        let t = tournament();
+        assert_eq!(t.current_round(0), None);
+        assert_eq!(t.current_round(10_000), Some(0));
+        assert_eq!(t.current_round(70_000), Some(1));
+        assert_eq!(t.current_round(130_000), None);
+        let endless = Tournament {
+            spec: NewTournament {
+                starts_at: u64::MAX - 1000,
+                ..t.spec.clone()
+            },
+            ..t.clone()
+        };
+        assert_eq!(endless.current_round(u64::MAX - 1), Some(0));
+        let sessions = vec![
+            // 50 seconds left, two hints
+            session("reds", A, 10_000, Some(20_000)),
+            // too late for the round
+            session("reds", B, 70_000, Some(140_000)),
+            session("blues", A, 10_000, Some(60_000)),
+            session("blues", B, 70_000, Some(80_000)),
+            // the setup of another round
+            session("blues", A, 70_000, Some(75_000)),
+        ];
+        assert_eq!(
+            t.standings(&sessions),
+            vec![
+                Standing {
+                    team: "blues".to_string(),
+                    score: 90 + 130,
+                    rounds_solved: 2,
+                    hints_used: 4,
+                },
+                Standing {
+                    team: "reds".to_string(),
+                    score: 130,
+                    rounds_solved: 1,
+                    hints_used: 2,
+                },
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn test_tournament() {
+        let token = Some("secret".to_string());
+        let app = build_app_from(build_all(), Arc::new(MemoryStorage::new()), token);
+        let spec = NewTournament {
+            name: "club".to_string(),
+            setups: vec![A, B],
+            starts_at: now_millis() - 1000,
+            round_millis: 600_000,
+        };
+        let resp = app
+            .post("/tournaments")
+            .body_string(serde_yaml::to_string(&spec).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 403);
+        let status: TournamentStatus = serde_yaml::from_str(
+            &app.post("/tournaments")
+                .header("Authorization", "Bearer secret")
+                .body_string(serde_yaml::to_string(&spec).unwrap())
+                .recv_string()
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(status.current_round, Some(0));
+        let url = format!("/tournaments/{}", status.tournament_id);
+        let mut tokens = vec![];
+        for team in ["reds", "blues"] {
+            let registered: RegisteredTeam = serde_yaml::from_str(
+                &app.post(format!("{}/teams", url))
+                    .body_string(format!("name: {}", team))
+                    .recv_string()
+                    .await
+                    .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(registered.name, team);
+            tokens.push(registered.token);
+        }
+        let resp = app
+            .post(format!("{}/teams", url))
+            .body_string("name: reds".to_string())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 409);
+
+        // a token only plays for the team it was issued to
+        for query in [
+            format!("player=reds&team_token={}", tokens[1]),
+            format!("player=reds&team_token={}", Uuid::new_v4()),
+            format!("team_token={}", tokens[0]),
+            "player=reds&team_token=reds".to_string(),
+        ] {
+            let resp = app.post(format!("/game/{}?{}", A, query)).await.unwrap();
+            assert_eq!(resp.status(), 403, "{}", query);
+        }
+
+        // the blues solve the first round through the usual game flow; the
+        // reds without their token and a player outside the tournament do not
+        // show in the standings or the replay
+        let setup = app.state().setup(&A).unwrap().setup;
+        for query in [
+            format!("player=blues&team_token={}", tokens[1]),
+            "player=reds".to_string(),
+            "player=greens".to_string(),
+        ] {
+            let resp = app.post(format!("/game/{}?{}", A, query)).await.unwrap();
+            let session_id = resp[SESSION_HEADER].last().to_string();
+            app.post(format!("/game/{}/check", A))
+                .header(SESSION_HEADER, session_id)
+                .body_string(serde_yaml::to_string(&Check::from_cells(&cells(&setup))).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let status: TournamentStatus =
+            serde_yaml::from_str(&app.get(&url).recv_string().await.unwrap()).unwrap();
+        assert_eq!(status.teams, vec!["reds", "blues"]);
+        assert_eq!(status.standings[0].team, "blues");
+        assert_eq!(status.standings[0].rounds_solved, 1);
+        assert_eq!(status.standings[1].score, 0);
+
+        let frames: Vec<ReplayFrame> = serde_yaml::from_str(
+            &app.get(format!("{}/replay", url))
+                .recv_string()
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].standings, status.standings);
+
+        let resp = app
+            .get(format!("/tournaments/{}", Uuid::new_v4()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 404);
+        let resp = app
+            .post("/tournaments")
+            .header("Authorization", "Bearer secret")
+            .body_string("name: x\nsetups: []\nstarts_at: 0\nround_millis: 1".to_string())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 422);
+        let late = NewTournament {
+            starts_at: u64::MAX - 1000,
+            ..spec
+        };
+        let resp = app
+            .post("/tournaments")
+            .header("Authorization", "Bearer secret")
+            .body_string(serde_yaml::to_string(&late).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 422);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::openapi::{Doc, Routes};
+use crate::setups::{OccupiedCells, Setup, SetupFormat};
+use crate::{
+    deduce, hint_limit, hints_for, number_of_hints, record_check, record_hints, show_board,
+    start_session, validate, yaml_response, HintQuery, NewGameQuery, State, CT_YAML,
+    SESSION_HEADER,
+};
+use bimaru_protocol::v2::{Check, CheckResult, Coord, Error, Hints, NewGame};
+use std::str::FromStr;
+use tide::Request;
+use uuid::Uuid;
+
+pub fn game_routes(routes: &mut Routes) {
+    routes.post(
+        "/game/:setup_id",
+        new_game,
+        Doc::new("Start a game on a setup")
+            .query::<NewGameQuery>()
+            .response::<NewGame>(201, CT_YAML)
+            .response::<Error>(403, CT_YAML)
+            .response::<Error>(404, CT_YAML),
+    );
+    routes.post(
+        "/game/:setup_id/check",
+        check,
+        Doc::new("Check a solution and count correct and wrong cells")
+            .header(SESSION_HEADER, false)
+            .body::<Check>(CT_YAML)
+            .response::<CheckResult>(200, CT_YAML)
+            .response::<Error>(400, CT_YAML)
+            .response::<Error>(404, CT_YAML),
+    );
+    routes.get(
+        "/game/:setup_id/hint",
+        hint,
+        Doc::new("Reveal ship cells")
+            .query::<HintQuery>()
+            .header(SESSION_HEADER, false)
+            .response::<Hints>(200, CT_YAML)
+            .response::<Error>(404, CT_YAML),
+    );
+    routes.get(
+        "/game/:setup_id/board",
+        show_board,
+        Doc::new("Render the solved board").text(200).text(404),
+    );
+    routes.post(
+        "/game/:setup_id/validate",
+        validate::validate_partial,
+        Doc::new("Check a partial solution for contradictions")
+            .header(SESSION_HEADER, false)
+            .body::<validate::PartialSolution>(CT_YAML)
+            .response::<validate::Validation>(200, CT_YAML)
+            .text(404)
+            .text(415)
+            .text(422),
+    );
+}
+
+pub fn check_result(setup: &Setup, check: &Check) -> CheckResult {
+    let mut coords: Vec<Coord> = check.coords.clone();
+    coords.sort_by_key(|c| (c.row, c.col));
+    coords.dedup();
+    let correct = coords
+        .iter()
+        .filter(|c| c.row < 10 && c.col < 10 && setup[c.row][c.col] > 0)
+        .count();
+    let wrong = coords.len() - correct;
+    CheckResult {
+        solved: correct == 20 && wrong == 0,
+        correct,
+        wrong,
+        missing: 20 - correct,
+    }
+}
+
+fn error(status: u16, text: &str) -> tide::Result {
+    yaml_response(
+        status,
+        CT_YAML,
+        &Error {
+            error: text.to_string(),
+        },
+    )
+}
+
+fn setup_of(req: &Request<State>) -> Result<(Uuid, SetupFormat), &'static str> {
+    let game_setup_str: &str = req.param("setup_id").unwrap_or_default();
+    match Uuid::from_str(game_setup_str) {
+        Ok(game_setup_id) => match req.state().setup(&game_setup_id) {
+            Some(sf) => Ok((game_setup_id, sf)),
+            None => Err("Unknown game setup"),
+        },
+        Err(_) => Err("Game setup id not found"),
+    }
+}
+
+async fn new_game(req: Request<State>) -> tide::Result {
+    let (game_setup_id, sf) = match setup_of(&req) {
+        Ok(found) => found,
+        Err(text) => return error(404, text),
+    };
+    let session_id = match start_session(&req, game_setup_id)? {
+        Some(session_id) => session_id,
+        None => return error(403, "Team token does not belong to the player"),
+    };
+    let mut resp = yaml_response(
+        201,
+        CT_YAML,
+        &NewGame {
+            game_setup_id,
+            session_id,
+            number_of_hints: number_of_hints(&sf),
+            occupied_rows: sf.setup.occupied_rows(),
+            occupied_cols: sf.setup.occupied_cols(),
+            difficulty: Some(deduce::rate(&sf)),
+        },
+    )?;
+    resp.insert_header(SESSION_HEADER, session_id.to_string());
+    Ok(resp)
+}
+
+async fn hint(req: Request<State>) -> tide::Result {
+    let (game_setup_id, sf) = match setup_of(&req) {
+        Ok(found) => found,
+        Err(text) => return error(404, text),
+    };
+    let coords = hints_for(&sf, hint_limit(&req));
+    record_hints(&req, game_setup_id, coords.len() as u8)?;
+    yaml_response(200, CT_YAML, &Hints { coords })
+}
+
+async fn check(mut req: Request<State>) -> tide::Result {
+    let (game_setup_id, sf) = match setup_of(&req) {
+        Ok(found) => found,
+        Err(text) => return error(404, text),
+    };
+    let body_str = match req.body_string().await {
+        Ok(body_str) => body_str,
+        Err(_) => return error(400, "Could not read the request"),
+    };
+    match serde_yaml::from_str::<Check>(&body_str) {
+        Ok(entity) => {
+            let result = check_result(&sf.setup, &entity);
+            record_check(&req, game_setup_id, result.solved)?;
+            yaml_response(200, CT_YAML, &result)
+        }
+        Err(e) => error(400, &e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_app;
+    use crate::setups::{cells, GAME_2};
+    use crate::DEPRECATION_HEADER;
+    use tide_testing::TideTestingExt;
+
+    #[test]
+    fn test_check_result() {
+        let solution = Check::from_cells(&cells(&GAME_2));
+        assert_eq!(
+            check_result(&GAME_2, &solution),
+            CheckResult {
+                solved: true,
+                correct: 20,
+                wrong: 0,
+                missing: 0
+            }
+        );
+        let mut partial = Check::from_cells(&cells(&GAME_2)[..5]);
+        partial.coords.push(Coord { row: 9, col: 0 });
+        partial.coords.push(Coord { row: 12, col: 0 });
+        assert_eq!(
+            check_result(&GAME_2, &partial),
+            CheckResult {
+                solved: false,
+                correct: 5,
+                wrong: 2,
+                missing: 15
+            }
+        );
+    }
+
+    #[async_std::test]
+    async fn test_versions() {
+        let app = build_app();
+        // nested lists in v1 for create and for hints respectively
+        let nested_create = "37073150-f43d-4609-94ec-dcbeffcb472a";
+        let nested = "dd8fb490-72c8-485b-aeea-537b9be34e4b";
+        for url in [
+            format!("/game/{}", nested_create),
+            format!("/v1/game/{}", nested_create),
+        ] {
+            let resp = app.post(url).await.unwrap();
+            assert_eq!(resp.status(), 201);
+            assert_eq!(
+                resp.content_type().unwrap().essence(),
+                bimaru_protocol::CT_YAML_NESTED
+            );
+            assert_eq!(resp[DEPRECATION_HEADER].last().as_str(), "true");
+        }
+
+        let mut resp = app
+            .post(format!("/v2/game/{}", nested_create))
+            .await
+            .unwrap();
+        assert_eq!(resp.content_type().unwrap().essence(), crate::CT_YAML);
+        assert!(resp.header(DEPRECATION_HEADER).is_none());
+        let game = serde_yaml::from_str::<NewGame>(&resp.body_string().await.unwrap()).unwrap();
+        let sf = app.state().setup(&game.game_setup_id).unwrap();
+        let setup = sf.setup;
+        assert_eq!(game.occupied_rows, setup.occupied_rows());
+        assert_eq!(game.difficulty, Some(deduce::rate(&sf)));
+        let result = app
+            .post(format!("/v2/game/{}/check", nested_create))
+            .header(SESSION_HEADER, game.session_id.to_string())
+            .body_string(serde_yaml::to_string(&Check::from_cells(&cells(&setup))).unwrap())
+            .recv_string()
+            .await
+            .unwrap();
+        assert!(serde_yaml::from_str::<CheckResult>(&result).unwrap().solved);
+        let session = app.state().storage.session(&game.session_id).unwrap();
+        assert!(session.completed_at.is_some());
+
+        let hints = app
+            .get(format!("/v2/game/{}/hint?limit=2", nested))
+            .recv_string()
+            .await
+            .unwrap();
+        let sf = app.state().setup(&Uuid::from_str(nested).unwrap()).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Hints>(&hints).unwrap().coords,
+            hints_for(&sf, 2)
+        );
+        // on v2 the advertised number of hints leave the setup as the one solution
+        let advertised = app
+            .get(format!(
+                "/v2/game/{}/hint?limit={}",
+                nested,
+                number_of_hints(&sf)
+            ))
+            .recv_string()
+            .await
+            .unwrap();
+        let mut puzzle = crate::search::Puzzle::from_setup(&sf.setup);
+        for c in serde_yaml::from_str::<Hints>(&advertised).unwrap().coords {
+            puzzle.reveal(c.row, c.col, crate::search::Known::Ship);
+        }
+        assert_eq!(crate::search::count_solutions(&puzzle, 2), 1);
+
+        let resp = app
+            .post(format!("/v2/game/{}/check", nested))
+            .body_string("-".to_string())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400);
+        let resp = app.get("/v2/leaderboard").await.unwrap();
+        assert_eq!(resp.status(), 200);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::setups::{OccupiedCells, Setup};
+use crate::{
+    illegal_request, not_found, served_hints, session_of, unprocessable, yaml_response, Coord,
+    State, CT_YAML,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tide::Request;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PartialSolution {
+    #[serde(default)]
+    pub ships: Vec<Coord>,
+    #[serde(default)]
+    pub water: Vec<Coord>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct Validation {
+    pub consistent: bool,
+    pub rows_over: Vec<usize>,
+    pub cols_over: Vec<usize>,
+    pub contradicted_hints: Vec<Coord>,
+    pub touching: Vec<Coord>,
+}
+
+pub fn validate(setup: &Setup, hints: &[Coord], partial: &PartialSolution) -> Validation {
+    let mut ships = [[false; 10]; 10];
+    for c in partial.ships.iter() {
+        ships[c.row][c.col] = true;
+    }
+    let mut rows = [0u8; 10];
+    let mut cols = [0u8; 10];
+    for (i, row) in ships.iter().enumerate() {
+        for (j, ship) in row.iter().enumerate() {
+            if *ship {
+                rows[i] += 1;
+                cols[j] += 1;
+            }
+        }
+    }
+    let over = |marked: [u8; 10], expected: [u8; 10]| -> Vec<usize> {
+        (0..10).filter(|i| marked[*i] > expected[*i]).collect()
+    };
+    let rows_over = over(rows, setup.occupied_rows());
+    let cols_over = over(cols, setup.occupied_cols());
+
+    let contradicted_hints: Vec<Coord> = hints
+        .iter()
+        .filter(|h| partial.water.contains(h))
+        .copied()
+        .collect();
+
+    let mut touching: Vec<Coord> = vec![];
+    for (i, row) in ships.iter().enumerate() {
+        for (j, ship) in row.iter().enumerate() {
+            let diagonal = [
+                (i.wrapping_sub(1), j.wrapping_sub(1)),
+                (i.wrapping_sub(1), j + 1),
+                (i + 1, j.wrapping_sub(1)),
+                (i + 1, j + 1),
+            ];
+            if *ship
+                && diagonal
+                    .iter()
+                    .any(|&(r, c)| r < 10 && c < 10 && ships[r][c])
+            {
+                touching.push(Coord { row: i, col: j });
+            }
+        }
+    }
+
+    Validation {
+        consistent: rows_over.is_empty()
+            && cols_over.is_empty()
+            && contradicted_hints.is_empty()
+            && touching.is_empty(),
+        rows_over,
+        cols_over,
+        contradicted_hints,
+        touching,
+    }
+}
+
+pub async fn validate_partial(mut req: Request<State>) -> tide::Result {
+    let game_setup_str: &str = req.param("setup_id")?;
+    if let Ok(game_setup_id) = Uuid::from_str(game_setup_str) {
+        if let Some(setup) = req.state().setup(&game_setup_id) {
+            if let Ok(body_str) = req.body_string().await {
+                if let Ok(entity) = serde_yaml::from_str::<PartialSolution>(&body_str) {
+                    if entity
+                        .ships
+                        .iter()
+                        .chain(entity.water.iter())
+                        .any(|c| c.row > 9 || c.col > 9)
+                    {
+                        return unprocessable("Coordinate is off the board");
+                    }
+                    let hints_used = match session_of(&req, &game_setup_id) {
+                        Some(session) => session.hints_used,
+                        None => 0,
+                    };
+                    let hints = served_hints(&req, &setup, hints_used);
+                    yaml_response(200, CT_YAML, &validate(&setup.setup, &hints, &entity))
+                } else {
+                    illegal_request("Could not parse entity")
+                }
+            } else {
+                illegal_request("Could not read the request")
+            }
+        } else {
+            not_found("Unknown game setup")
+        }
+    } else {
+        not_found("Game setup id not found")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_hints;
+    use crate::setups::GAME_2;
+
+    #[test]
+    fn test_validate() {
+        let hints = find_hints(&GAME_2, 3);
+        let consistent = PartialSolution {
+            ships: vec![Coord { row: 6, col: 4 }, Coord { row: 0, col: 8 }],
+            water: vec![Coord { row: 0, col: 0 }],
+        };
+        assert!(validate(&GAME_2, &hints, &consistent).consistent);
+
+        let broken = PartialSolution {
+            ships: vec![
+                Coord { row: 0, col: 0 },
+                Coord { row: 0, col: 1 },
+                Coord { row: 1, col: 2 },
+            ],
+            water: vec![Coord { row: 6, col: 5 }],
+        };
+        assert_eq!(
+            validate(&GAME_2, &hints, &broken),
+            Validation {
+                consistent: false,
+                rows_over: vec![0],
+                cols_over: vec![0],
+                contradicted_hints: vec![Coord { row: 6, col: 5 }],
+                touching: vec![Coord { row: 0, col: 1 }, Coord { row: 1, col: 2 }],
+            }
+        );
+    }
+}
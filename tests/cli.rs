@@ -0,0 +1,48 @@
+use bimaru::setups::{build_all, cells, to_catalog};
+use bimaru::Check;
+use std::path::PathBuf;
+use std::process::Command;
+use uuid::Uuid;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bimaru-cli-{}-{}", name, Uuid::new_v4()))
+}
+
+fn run(args: &[&str]) -> i32 {
+    Command::new(env!("CARGO_BIN_EXE_bimaru-cli"))
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+#[test]
+fn test_verify_exit_codes() {
+    let setups = build_all();
+    let id = to_catalog(&setups)[0].id;
+    let sf = setups[&id];
+    let solution = temp_path("solution");
+    let solved = Check::from_cells(&cells(&sf.setup));
+    std::fs::write(&solution, sf.wire_format.encode(&solved).unwrap()).unwrap();
+    let wrong = temp_path("wrong");
+    let empty = Check::from_cells(&[]);
+    std::fs::write(&wrong, sf.wire_format.encode(&empty).unwrap()).unwrap();
+    let missing = temp_path("missing");
+    let id = id.to_string();
+    let solution_path = solution.to_str().unwrap();
+    let missing_path = missing.to_str().unwrap();
+
+    assert_eq!(run(&["verify", &id, solution_path]), 0);
+    assert_eq!(run(&["verify", &id, wrong.to_str().unwrap()]), 1);
+    assert_eq!(run(&["verify", &id, missing_path]), 2);
+    // an unreadable catalog fails before the solution is looked at
+    assert_eq!(
+        run(&["--catalog", missing_path, "verify", &id, solution_path]),
+        2
+    );
+
+    std::fs::remove_file(solution).unwrap();
+    std::fs::remove_file(wrong).unwrap();
+}